@@ -0,0 +1,416 @@
+use crate::{
+    date,
+    keymap::{clashes, parse_sequence, sequence_name, Action, Keymap, Profile},
+    size::Units,
+    theme::{color_index, hex_color, Depth, Paint, Palette},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use termion::event::Key;
+use toml::Spanned;
+
+// rows kept visible around the pointer unless the file says otherwise
+const SCROLLOFF: usize = 3;
+// characters of each hash shown in the list
+const HASH_CHARS: usize = 20;
+// how the modified column shows times, as for strftime
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// What the optional line numbers count.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Numbering {
+    // position in the list as sorted and filtered
+    #[default]
+    Display,
+    // position in the listing, staying with the entry
+    Absolute,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub scrolloff: usize,
+    pub units: Units,
+    pub hash_chars: usize,
+    pub time_format: String,
+    // Nerd Font icons in front of file types
+    pub icons: bool,
+    pub palette: Palette,
+    // colors the terminal can show, detected at startup rather than read from the file
+    pub depth: Depth,
+    // line numbers are shown from the start when set
+    pub numbers: Option<Numbering>,
+    // box-drawing lines around the table
+    pub frame: bool,
+    // the program name and version above the header
+    pub title: bool,
+    // details of the entry under the pointer beside the table, on terminals wide enough
+    pub pane: bool,
+    // lines of key hints under the footer, when there are rows to spare
+    pub hints: bool,
+    // clicks, the wheel and hovering, off leaves the terminal's own selection alone
+    pub mouse: bool,
+    // off draws on the main screen, so the table is left in the scrollback on exit
+    pub alt_screen: bool,
+    // selected and unselected glyphs, picked by the locale unless set
+    pub checkbox: Option<(String, String)>,
+    // entries already in the download directory are left out of the list
+    pub hide_local: bool,
+    // whole hashes in the hash column, only turned on with --full-hash
+    pub full_hash: bool,
+    // mock downloads and connections break down, only turned on with --mock-faults
+    pub mock_faults: bool,
+    // globs of --only and --exclude, only set on the command line
+    pub only: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: Keymap::default(),
+            scrolloff: SCROLLOFF,
+            units: Units::default(),
+            hash_chars: HASH_CHARS,
+            time_format: TIME_FORMAT.to_string(),
+            icons: false,
+            palette: Palette::default(),
+            depth: Depth::Ansi16,
+            numbers: None,
+            frame: false,
+            title: true,
+            pane: false,
+            hints: true,
+            mouse: true,
+            alt_screen: true,
+            checkbox: None,
+            hide_local: false,
+            full_hash: false,
+            mock_faults: false,
+            only: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    profile: Option<Profile>,
+    scrolloff: Option<usize>,
+    units: Option<Units>,
+    hash_chars: Option<usize>,
+    time_format: Option<String>,
+    icons: Option<bool>,
+    numbers: Option<Numbering>,
+    frame: Option<bool>,
+    title: Option<bool>,
+    pane: Option<bool>,
+    hints: Option<bool>,
+    mouse: Option<bool>,
+    alt_screen: Option<bool>,
+    checkbox: Option<(String, String)>,
+    hide_local: Option<bool>,
+    #[serde(default)]
+    keys: HashMap<Spanned<String>, Spanned<Keys>>,
+    #[serde(default)]
+    colors: HashMap<Spanned<String>, Spanned<Color>>,
+}
+
+// a single key or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+// a color name, `#rrggbb` or a 256-color index
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Color {
+    Name(String),
+    Index(i64),
+}
+
+/// `$XDG_CONFIG_HOME/leightbox/config.toml`, falling back to `~/.config`.
+pub fn path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join("leightbox").join("config.toml"))
+}
+
+/// Loads the config file, a missing file leaves everything at the defaults.
+///
+/// A `profile` given on the command line takes precedence over the one in the file.
+pub fn load(profile: Option<Profile>) -> Result<Config, Box<dyn Error>> {
+    let defaults = Config {
+        keymap: Keymap::new(profile.unwrap_or_default()),
+        ..Config::default()
+    };
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(defaults),
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(defaults),
+        Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+    };
+
+    parse(&path, &text, profile)
+}
+
+fn parse(path: &Path, text: &str, profile: Option<Profile>) -> Result<Config, Box<dyn Error>> {
+    let raw: RawConfig = toml::from_str(text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let line = |offset: usize| text[..offset].matches('\n').count() + 1;
+
+    // bindings in file order so errors point at the first offending line
+    let mut keys: Vec<_> = raw.keys.into_iter().collect();
+    keys.sort_by_key(|(name, _)| name.span().start);
+
+    let mut keymap = Keymap::new(profile.or(raw.profile).unwrap_or_default());
+    let mut bound: Vec<(Vec<Key>, Action, usize)> = Vec::new();
+
+    for (name, value) in keys {
+        let at = line(name.span().start);
+        let fail = |msg: String| format!("{} line {}: {}", path.display(), at, msg);
+
+        let action = Action::from_name(name.get_ref())
+            .ok_or_else(|| fail(format!("unknown action '{}'", name.get_ref())))?;
+
+        let names = match value.into_inner() {
+            Keys::One(key) => vec![key],
+            Keys::Many(keys) => keys,
+        };
+
+        let mut parsed = Vec::new();
+        for key_name in names {
+            let keys = parse_sequence(&key_name).map_err(fail)?;
+
+            // digits always build up a count
+            if let Key::Char('0'..='9') = keys[0] {
+                return Err(fail(format!("'{}' is reserved for counts", key_name)).into());
+            }
+
+            let clash = bound
+                .iter()
+                .find(|(other, a, _)| *a != action && clashes(other, &keys));
+            if let Some((other, a, other_at)) = clash {
+                let msg = format!(
+                    "'{}' clashes with '{}' bound to {} on line {}",
+                    key_name,
+                    sequence_name(other),
+                    a.name(),
+                    other_at
+                );
+                return Err(fail(msg).into());
+            }
+
+            bound.push((keys.clone(), action, at));
+            parsed.push(keys);
+        }
+
+        keymap.bind(action, parsed);
+    }
+
+    let mut palette = Palette::default();
+    for (name, value) in raw.colors {
+        let at = line(name.span().start);
+        let fail = |msg: String| format!("{} line {}: {}", path.display(), at, msg);
+
+        let paint = match value.into_inner() {
+            Color::Name(color) => color_index(&color)
+                .map(Paint::Index)
+                .or_else(|| hex_color(&color))
+                .ok_or_else(|| fail(format!("unknown color '{}' for {}", color, name.get_ref())))?,
+            Color::Index(i) => u8::try_from(i).map(Paint::Index).map_err(|_| {
+                fail(format!(
+                    "color {} for {} isn't between 0 and 255",
+                    i,
+                    name.get_ref()
+                ))
+            })?,
+        };
+
+        if !palette.set(name.get_ref(), paint) {
+            return Err(fail(format!("unknown color element '{}'", name.get_ref())).into());
+        }
+    }
+
+    if let Some(format) = &raw.time_format {
+        if date::format(0, format).is_none() {
+            let msg = format!("time_format '{}' doesn't show anything", format);
+            return Err(format!("{}: {}", path.display(), msg).into());
+        }
+    }
+
+    // empty or identical glyphs leave no way to tell selected rows apart
+    if let Some((on, off)) = &raw.checkbox {
+        if on.is_empty() || off.is_empty() || on == off {
+            return Err(format!("{}: checkbox needs two different glyphs", path.display()).into());
+        }
+    }
+
+    // rebinding its keys elsewhere could leave no way out
+    if keymap.keys(Action::Quit).is_empty() {
+        return Err(format!("{}: no key is bound to quit", path.display()).into());
+    }
+
+    Ok(Config {
+        keymap,
+        scrolloff: raw.scrolloff.unwrap_or(SCROLLOFF),
+        units: raw.units.unwrap_or_default(),
+        hash_chars: raw.hash_chars.unwrap_or(HASH_CHARS),
+        time_format: raw.time_format.unwrap_or_else(|| TIME_FORMAT.to_string()),
+        icons: raw.icons.unwrap_or_default(),
+        palette,
+        depth: Depth::Ansi16,
+        numbers: raw.numbers,
+        frame: raw.frame.unwrap_or_default(),
+        title: raw.title.unwrap_or(true),
+        pane: raw.pane.unwrap_or_default(),
+        hints: raw.hints.unwrap_or(true),
+        mouse: raw.mouse.unwrap_or(true),
+        alt_screen: raw.alt_screen.unwrap_or(true),
+        checkbox: raw.checkbox,
+        hide_local: raw.hide_local.unwrap_or_default(),
+        full_hash: false,
+        mock_faults: false,
+        only: Vec::new(),
+        exclude: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_text(text: &str) -> Result<Config, String> {
+        parse(Path::new("config.toml"), text, None).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn valid_file() {
+        let text = r##"
+profile = "emacs"
+scrolloff = 5
+units = "si"
+hash_chars = 12
+icons = true
+numbers = "absolute"
+checkbox = ["x", "."]
+
+[keys]
+toggle = "x"
+first = ["g g", "Home"]
+
+[colors]
+header = "#ff8000"
+stripe = 236
+"##;
+        let config = parse_text(text).unwrap();
+        assert_eq!(config.scrolloff, 5);
+        assert_eq!(config.units, Units::Si);
+        assert_eq!(config.hash_chars, 12);
+        assert!(config.icons);
+        assert_eq!(config.numbers, Some(Numbering::Absolute));
+        assert_eq!(config.checkbox, Some(("x".to_string(), ".".to_string())));
+
+        let keymap = &config.keymap;
+        assert_eq!(keymap.get(Key::Char('x')), Some(Action::Toggle));
+        assert_eq!(keymap.keys(Action::First).len(), 2);
+        // the profile from the file still adds the emacs keys
+        assert_eq!(keymap.get(Key::Ctrl('n')), Some(Action::MoveDown));
+
+        assert_eq!(config.palette.header, Paint::Rgb(255, 128, 0));
+        assert_eq!(config.palette.stripe, Some(Paint::Index(236)));
+        // anything the file leaves out keeps its default
+        assert_eq!(config.time_format, TIME_FORMAT);
+        assert!(config.title && config.mouse);
+    }
+
+    #[test]
+    fn empty_file_is_the_defaults() {
+        let config = parse_text("").unwrap();
+        assert_eq!(config.scrolloff, SCROLLOFF);
+        assert_eq!(config.hash_chars, HASH_CHARS);
+        assert_eq!(config.keymap.get(Key::Char('j')), Some(Action::MoveDown));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let err = parse_text("scrolloff = 2\ncolour = true\n").unwrap_err();
+        assert!(err.contains("unknown field `colour`"), "{}", err);
+        assert!(err.contains("line 2"), "{}", err);
+
+        let err = parse_text("[keys]\nfly = \"f\"\n").unwrap_err();
+        assert_eq!(err, "config.toml line 2: unknown action 'fly'");
+
+        let err = parse_text("[colors]\nborder = \"red\"\n").unwrap_err();
+        assert_eq!(err, "config.toml line 2: unknown color element 'border'");
+    }
+
+    #[test]
+    fn clashing_bindings() {
+        let text = "[keys]\ntoggle = \"x\"\n\nselect_all = [\"a\", \"x\"]\n";
+        let err = parse_text(text).unwrap_err();
+        assert_eq!(
+            err,
+            "config.toml line 4: 'x' clashes with 'x' bound to toggle on line 2"
+        );
+
+        // a prefix of another binding clashes as well
+        let err = parse_text("[keys]\nfirst = \"g g\"\nlast = \"g\"\n").unwrap_err();
+        assert!(
+            err.starts_with("config.toml line 3: 'g' clashes"),
+            "{}",
+            err
+        );
+
+        // one action may have as many keys as it likes
+        assert!(parse_text("[keys]\nquit = [\"q\", \"Q\"]\n").is_ok());
+    }
+
+    #[test]
+    fn bad_values_say_which_line() {
+        let text = "scrolloff = 1\n\n[keys]\nquit = \"q\"\ntoggle = \"Spcae\"\n";
+        let err = parse_text(text).unwrap_err();
+        assert_eq!(err, "config.toml line 5: unknown key 'Spcae'");
+
+        let err = parse_text("[keys]\ntoggle = \"5\"\n").unwrap_err();
+        assert_eq!(err, "config.toml line 2: '5' is reserved for counts");
+
+        let err = parse_text("[colors]\n\nlist = \"#12345\"\n").unwrap_err();
+        assert_eq!(err, "config.toml line 3: unknown color '#12345' for list");
+
+        let err = parse_text("[colors]\nlist = 300\n").unwrap_err();
+        assert_eq!(
+            err,
+            "config.toml line 2: color 300 for list isn't between 0 and 255"
+        );
+    }
+
+    #[test]
+    fn settings_that_leave_nothing_to_see() {
+        let err = parse_text("checkbox = [\"x\", \"x\"]\n").unwrap_err();
+        assert_eq!(err, "config.toml: checkbox needs two different glyphs");
+
+        let err = parse_text("time_format = \"\"\n").unwrap_err();
+        assert_eq!(err, "config.toml: time_format '' doesn't show anything");
+
+        let err = parse_text("[keys]\ntoggle = \"q\"\nquit = []\n").unwrap_err();
+        assert_eq!(err, "config.toml: no key is bound to quit");
+    }
+}
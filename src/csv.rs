@@ -0,0 +1,153 @@
+// delimiters tried when none is given, in the order ties are broken
+const DELIMITERS: [char; 3] = ['\t', ',', ';'];
+
+/// The delimiter that appears most often on the first line with anything on it.
+pub fn detect(text: &str) -> char {
+    let line = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+
+    // on a tie the earlier one wins, max_by_key would take the later
+    DELIMITERS
+        .into_iter()
+        .rev()
+        .max_by_key(|d| line.matches(*d).count())
+        .unwrap_or(',')
+}
+
+/// The records of `text` with the line each starts on, blank lines left out.
+///
+/// Fields may be quoted to hold the delimiter, quotes written twice or line breaks.
+pub fn records(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    // whether the current field was quoted, and whether the quote is still open
+    let (mut quoted, mut open) = (false, false);
+    let (mut line, mut start) = (1, 1);
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if open && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if open => open = false,
+            '"' if field.trim().is_empty() && !quoted => {
+                field.clear();
+                (quoted, open) = (true, true);
+            }
+            '"' => return Err(format!("line {}: quote in the middle of a field", line)),
+            '\n' if open => {
+                field.push('\n');
+                line += 1;
+            }
+            '\r' if !open && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                // a line with nothing on it isn't a record with one empty field
+                let blank = fields.is_empty() && field.trim().is_empty() && !quoted;
+                fields.push(end_field(&mut field, quoted));
+                if !blank {
+                    records.push((start, fields));
+                }
+                fields = Vec::new();
+                quoted = false;
+                line += 1;
+                start = line;
+            }
+            c if c == delimiter && !open => {
+                fields.push(end_field(&mut field, quoted));
+                quoted = false;
+            }
+            ' ' | '\t' if quoted && !open => {}
+            _ if quoted && !open => {
+                return Err(format!("line {}: text after a closing quote", line));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if open {
+        return Err(format!("line {}: quote isn't closed", start));
+    }
+    // the last line may not end in a line break
+    if !field.trim().is_empty() || !fields.is_empty() || quoted {
+        fields.push(end_field(&mut field, quoted));
+        records.push((start, fields));
+    }
+
+    Ok(records)
+}
+
+/// Takes the field, unquoted ones lose the spaces around them.
+fn end_field(field: &mut String, quoted: bool) -> String {
+    let text = std::mem::take(field);
+    match quoted {
+        true => text,
+        false => text.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(text: &str, delimiter: char) -> Vec<Vec<String>> {
+        let records = records(text, delimiter).unwrap();
+        records.into_iter().map(|(_, fields)| fields).collect()
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(fields("\"a,b.txt\",10\n", ','), [["a,b.txt", "10"]]);
+        assert_eq!(
+            fields("\"say \"\"hi\"\".txt\",1\n", ','),
+            [["say \"hi\".txt", "1"]]
+        );
+        // a line break inside quotes belongs to the field, the next record starts after it
+        let records = records("\"two\nlines\",1\nnext,2\n", ',').unwrap();
+        assert_eq!(
+            records[0],
+            (1, vec!["two\nlines".to_string(), "1".to_string()])
+        );
+        assert_eq!(records[1], (3, vec!["next".to_string(), "2".to_string()]));
+    }
+
+    #[test]
+    fn blank_lines_and_short_rows() {
+        let records = records("name,size,hash\n\n  \na.txt,1\r\nb.txt,2,ff", ',').unwrap();
+        let lines: Vec<usize> = records.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 4, 5]);
+        // the header is a record like any other, and a missing hash cell is just missing
+        assert_eq!(records[0].1, ["name", "size", "hash"]);
+        assert_eq!(records[1].1, ["a.txt", "1"]);
+        assert_eq!(records[2].1, ["b.txt", "2", "ff"]);
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(
+            records("a,1\nb\"c,2\n", ',').unwrap_err(),
+            "line 2: quote in the middle of a field"
+        );
+        assert_eq!(
+            records("a,1\n\"b\"c,2\n", ',').unwrap_err(),
+            "line 2: text after a closing quote"
+        );
+        assert_eq!(
+            records("a,1\n\n\"b,2\n", ',').unwrap_err(),
+            "line 3: quote isn't closed"
+        );
+    }
+
+    #[test]
+    fn detect_delimiters() {
+        assert_eq!(detect("a.txt,10,ff\n"), ',');
+        assert_eq!(detect("a.txt;10;ff\n"), ';');
+        assert_eq!(detect("a.txt\t10\tff\n"), '\t');
+        // the first line with anything on it decides, a comma in a name doesn't outvote
+        assert_eq!(detect("\n\na,b.txt;10;ff\nc,d,e\n"), ';');
+        // ties go to the tab, then the comma
+        assert_eq!(detect("a,b\tc\n"), '\t');
+        assert_eq!(detect("a;b,c\n"), ',');
+    }
+}
@@ -0,0 +1,26 @@
+use std::ffi::CString;
+
+// longest text a format may produce
+const MAX_LEN: usize = 256;
+
+/// `secs` since the epoch in local time, formatted like `strftime` does.
+///
+/// Returns `None` for formats that produce nothing or contain a NUL.
+pub fn format(secs: i64, format: &str) -> Option<String> {
+    let format = CString::new(format).ok()?;
+    let time = secs as libc::time_t;
+
+    // SAFETY: tm is plain data that localtime_r fills in, or leaves alone on failure
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+
+    let mut buf = [0u8; MAX_LEN];
+    let n = unsafe { libc::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), &tm) };
+    if n == 0 {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&buf[..n]).into_owned())
+}
@@ -0,0 +1,123 @@
+// compressions that are usually put on a tarball, `foo.tar.gz` is a `tar.gz` and not a `gz`
+const TAR_SUFFIXES: [&str; 7] = ["gz", "bz2", "xz", "zst", "lz", "lzma", "z"];
+
+/// What kind of file a name is, for icons and sorting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Archive,
+    DiskImage,
+    Document,
+    Text,
+    Image,
+    Audio,
+    Video,
+    Code,
+    Other,
+}
+
+impl Kind {
+    pub fn of(extension: Option<&str>) -> Self {
+        let extension = match extension {
+            Some(ext) => ext,
+            None => return Kind::Other,
+        };
+
+        // multi-part ones are classified by their last part
+        match extension.rsplit('.').next().unwrap_or(extension) {
+            "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "zip" | "7z" | "rar" | "lz" | "lzma"
+            | "z" => Kind::Archive,
+            "iso" | "img" | "dmg" | "qcow2" | "vdi" | "vmdk" => Kind::DiskImage,
+            "pdf" | "epub" | "doc" | "docx" | "odt" | "xls" | "xlsx" | "ods" => Kind::Document,
+            "txt" | "md" | "log" | "csv" | "json" | "toml" | "yaml" | "yml" | "xml" => Kind::Text,
+            "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg" | "bmp" | "tiff" => Kind::Image,
+            "mp3" | "flac" | "ogg" | "opus" | "wav" | "m4a" => Kind::Audio,
+            "mp4" | "mkv" | "webm" | "avi" | "mov" => Kind::Video,
+            "rs" | "c" | "h" | "cpp" | "py" | "js" | "ts" | "go" | "sh" | "java" => Kind::Code,
+            _ => Kind::Other,
+        }
+    }
+
+    /// Nerd Font glyph for the kind.
+    pub fn icon(self) -> char {
+        match self {
+            Kind::Archive => '\u{f410}',
+            Kind::DiskImage => '\u{f0a0}',
+            Kind::Document => '\u{f1c1}',
+            Kind::Text => '\u{f15c}',
+            Kind::Image => '\u{f1c5}',
+            Kind::Audio => '\u{f1c7}',
+            Kind::Video => '\u{f1c8}',
+            Kind::Code => '\u{f121}',
+            Kind::Other => '\u{f15b}',
+        }
+    }
+}
+
+/// The extension of `name` in lowercase, with compressed tarballs kept whole like `tar.gz`.
+///
+/// Dotfiles like `.bashrc` and names ending in a dot don't have one.
+pub fn extension(name: &str) -> Option<String> {
+    // the leading dot of a dotfile doesn't start an extension
+    let stem = name.trim_start_matches('.');
+    let (rest, ext) = stem.rsplit_once('.')?;
+    if ext.is_empty() || rest.is_empty() {
+        return None;
+    }
+
+    let ext = ext.to_lowercase();
+    match rest.rsplit_once('.') {
+        Some((base, tar))
+            if !base.is_empty()
+                && tar.eq_ignore_ascii_case("tar")
+                && TAR_SUFFIXES.contains(&ext.as_str()) =>
+        {
+            Some(format!("tar.{}", ext))
+        }
+        _ => Some(ext),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(name: &str) -> Option<String> {
+        extension(name)
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(ext("notes.txt").as_deref(), Some("txt"));
+        assert_eq!(ext("photo.JPG").as_deref(), Some("jpg"));
+        assert_eq!(ext("README"), None);
+        assert_eq!(ext("trailing."), None);
+    }
+
+    #[test]
+    fn dotfiles() {
+        assert_eq!(ext(".bashrc"), None);
+        assert_eq!(ext("..hidden"), None);
+        assert_eq!(ext(".config.toml").as_deref(), Some("toml"));
+    }
+
+    #[test]
+    fn tarballs() {
+        assert_eq!(ext("a.tar.gz").as_deref(), Some("tar.gz"));
+        assert_eq!(ext("X.TAR.XZ").as_deref(), Some("tar.xz"));
+        assert_eq!(ext("backup.2024.tar.zst").as_deref(), Some("tar.zst"));
+        // a dotfile named after the suffix has nothing in front of the tar
+        assert_eq!(ext(".tar.gz").as_deref(), Some("gz"));
+        // only compressions stick to a tar
+        assert_eq!(ext("a.tar.pdf").as_deref(), Some("pdf"));
+        assert_eq!(ext("a.zip.gz").as_deref(), Some("gz"));
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Kind::of(Some("tar.gz")), Kind::Archive);
+        assert_eq!(Kind::of(Some("toml")), Kind::Text);
+        assert_eq!(Kind::of(Some("mkv")), Kind::Video);
+        assert_eq!(Kind::of(Some("unknown")), Kind::Other);
+        assert_eq!(Kind::of(None), Kind::Other);
+    }
+}
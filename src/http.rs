@@ -0,0 +1,283 @@
+use std::{
+    cmp::min,
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+// how long connecting, and each read or write after it, may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+// how long the whole request may take, however steadily the server trickles out its answer
+const DEADLINE: Duration = Duration::from_secs(60);
+// largest response that's read, a listing of a million files fits several times over
+const MAX_RESPONSE: usize = 256 * 1024 * 1024;
+
+/// The parts of an `http://` URL needed to fetch it.
+///
+/// There's no TLS, so `https://` URLs are turned down when the arguments are parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = match url.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some(("https", _)) => {
+                return Err(
+                    "https isn't supported, only plain http:// URLs can be fetched, \
+                     an https listing can be piped in with --stdin-format json"
+                        .to_string(),
+                )
+            }
+            Some((scheme, _)) => return Err(format!("unsupported scheme '{}'", scheme)),
+            None => return Err("expected a URL starting with http://".to_string()),
+        };
+
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        // a query without a path still asks for the root
+        let path = match path.starts_with('?') {
+            true => format!("/{}", path),
+            false => path.to_string(),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            // the colons of a bracketed IPv6 address aren't a port
+            Some((host, port)) if !port.contains(']') => {
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| format!("'{}' isn't a valid port", port))?;
+                (host, port)
+            }
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err("missing host".to_string());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.split('#').next().unwrap_or("/").to_string(),
+        })
+    }
+}
+
+/// The body of a successful GET of `url`, anything but a 200 is an error.
+pub fn get(url: &Url) -> Result<String, String> {
+    let addr = (url.host.trim_matches(['[', ']']), url.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("can't resolve {}: {}", url.host, e))?
+        .next()
+        .ok_or_else(|| format!("can't resolve {}", url.host))?;
+    let started = Instant::now();
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+        .map_err(|e| format!("can't connect to {}: {}", url.host, e))?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok();
+
+    // the default port is left out of the host header, like browsers do
+    let host = match url.port {
+        80 => url.host.clone(),
+        port => format!("{}:{}", url.host, port),
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}/{}\r\nAccept: application/json\r\n\
+         Connection: close\r\n\r\n",
+        url.path,
+        host,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("can't send the request: {}", e))?;
+
+    let response = read_response(&mut stream, started + DEADLINE, MAX_RESPONSE)?;
+
+    body(&response)
+}
+
+/// Reads until the server closes the connection, giving up at `deadline` or past `limit` bytes.
+fn read_response(
+    stream: &mut TcpStream,
+    deadline: Instant,
+    limit: usize,
+) -> Result<Vec<u8>, String> {
+    // the connection is closed after the response, so it ends at the end of the stream
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err("the server took too long to answer".to_string());
+        }
+        stream.set_read_timeout(Some(min(left, TIMEOUT))).ok();
+
+        let read = match stream.read(&mut chunk) {
+            Ok(0) => return Ok(response),
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err("the server took too long to answer".to_string())
+            }
+            Err(e) => return Err(format!("can't read the response: {}", e)),
+        };
+        if response.len() + read > limit {
+            return Err(format!(
+                "the response is larger than {} MiB",
+                limit / (1024 * 1024)
+            ));
+        }
+        response.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// The body of a raw response, checking its status and undoing a chunked encoding.
+fn body(response: &[u8]) -> Result<String, String> {
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("the response ended before its headers did")?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let mut body = &response[end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap_or_default();
+    let mut parts = status.splitn(3, ' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(version), Some("200"), _) if version.starts_with("HTTP/") => {}
+        (Some(version), Some(code), reason) if version.starts_with("HTTP/") => {
+            return Err(format!(
+                "the server answered {} {}",
+                code,
+                reason.unwrap_or("")
+            ));
+        }
+        _ => return Err("not an HTTP response".to_string()),
+    }
+
+    let header = |name: &str| {
+        head.split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    };
+    let chunked = header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+    let body = match chunked {
+        true => dechunk(body)?,
+        false => {
+            if let Some(len) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+                if body.len() < len {
+                    return Err("the response ended early".to_string());
+                }
+                body = &body[..len];
+            }
+            body.to_vec()
+        }
+    };
+
+    String::from_utf8(body).map_err(|_| "the response isn't UTF-8".to_string())
+}
+
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("the response ended early")?;
+        // extensions after a semicolon don't matter here
+        let size = String::from_utf8_lossy(&data[..line_end]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| "bad chunk in the response")?;
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size + 2 {
+            return Err("the response ended early".to_string());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn parse_urls() {
+        let url = Url::parse("http://example.com:8080/files.json?v=2#top").unwrap();
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/files.json?v=2");
+
+        let url = Url::parse("http://[::1]").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("[::1]", 80, "/")
+        );
+    }
+
+    #[test]
+    fn https_is_turned_down() {
+        let error = Url::parse("https://example.com/files.json").unwrap_err();
+        assert!(error.starts_with("https isn't supported"), "{}", error);
+    }
+
+    #[test]
+    fn chunked_body() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         4\r\n[1,2\r\n1;ext\r\n]\r\n0\r\n\r\n";
+        assert_eq!(body(response).unwrap(), "[1,2]");
+        assert_eq!(
+            body(b"HTTP/1.1 404 Not Found\r\n\r\n").unwrap_err(),
+            "the server answered 404 Not Found"
+        );
+    }
+
+    // a connection to a local server that sends `data` and then waits for `linger`
+    fn serve(data: Vec<u8>, linger: Duration) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(&data).ok();
+            thread::sleep(linger);
+        });
+
+        TcpStream::connect(addr).unwrap()
+    }
+
+    #[test]
+    fn responses_are_bounded() {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut stream = serve(vec![b'x'; 4096], Duration::ZERO);
+        assert_eq!(
+            read_response(&mut stream, deadline, 4096).unwrap().len(),
+            4096
+        );
+
+        let mut stream = serve(vec![b'x'; 4097], Duration::ZERO);
+        assert!(read_response(&mut stream, deadline, 4096).is_err());
+    }
+
+    #[test]
+    fn responses_have_a_deadline() {
+        let mut stream = serve(b"HTTP/1.1 200 OK\r\n".to_vec(), Duration::from_secs(5));
+        let deadline = Instant::now() + Duration::from_millis(200);
+        assert_eq!(
+            read_response(&mut stream, deadline, MAX_RESPONSE).unwrap_err(),
+            "the server took too long to answer"
+        );
+    }
+}
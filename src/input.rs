@@ -0,0 +1,302 @@
+use std::{
+    cmp::min,
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    str,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use termion::event::{parse_event, Event, Key, MouseEvent};
+
+// how long an incomplete escape sequence may wait for its remaining bytes
+const SEQ_TIMEOUT: Duration = Duration::from_millis(30);
+
+// read and write ends of the pipe that other threads wake `Input::wait` with
+static WAKE: OnceLock<(libc::c_int, libc::c_int)> = OnceLock::new();
+
+/// Wakes the main loop if it's waiting for input, for threads that just sent it something.
+pub fn wake() {
+    if let Some((_, write)) = WAKE.get() {
+        unsafe { libc::write(*write, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Puts the controlling terminal on stdin, once whatever was piped into it has been read.
+///
+/// The rest of the program reads keys from stdin, so it carries on as if started from the
+/// terminal.
+pub fn use_tty() -> io::Result<()> {
+    let tty = File::open("/dev/tty")?;
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Non-blocking terminal input that only hands complete byte sequences to termion.
+///
+/// Bytes are read as they arrive, so a multi-byte sequence (arrow keys, mouse reports,
+/// UTF-8) may be split across loop iterations. Parsing such a fragment directly would drop
+/// the escape and leak the rest as plain characters.
+pub struct Input {
+    buf: Vec<u8>,
+    pending: Option<Instant>,
+    peeked: Option<Event>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        // without the pipe only input and timeouts end a wait
+        WAKE.get_or_init(|| {
+            let mut fds = [-1; 2];
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+            (fds[0], fds[1])
+        });
+
+        Self {
+            buf: Vec::new(),
+            pending: None,
+            peeked: None,
+        }
+    }
+
+    /// Blocks until there's input, another thread calls `wake`, or `timeout` has passed.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        if self.peeked.is_some() || (!self.buf.is_empty() && seq_len(&self.buf).is_some()) {
+            return Ok(());
+        }
+
+        // an unfinished sequence is given up on sooner
+        let timeout = match self.pending {
+            Some(since) => min(timeout, SEQ_TIMEOUT.saturating_sub(since.elapsed())),
+            None => timeout,
+        };
+        let mut fds = vec![poll_fd(libc::STDIN_FILENO)];
+        if let Some((read, _)) = WAKE.get().filter(|(read, _)| *read >= 0) {
+            fds.push(poll_fd(*read));
+        }
+
+        // rounded up, so a wait for something due in half a millisecond doesn't spin
+        let ms = min(timeout.as_micros().div_ceil(1000), i32::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms) } < 0 {
+            let e = io::Error::last_os_error();
+            // a signal landing on this thread is as good as a wake
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(()),
+                _ => Err(e),
+            };
+        }
+
+        // the wake bytes were only there to end the wait
+        if let Some(fd) = fds.get(1).filter(|fd| fd.revents & libc::POLLIN != 0) {
+            let mut drain = [0u8; 64];
+            while unsafe { libc::read(fd.fd, drain.as_mut_ptr().cast(), drain.len()) } > 0 {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next event only if it satisfies `f`, keeping it for `next` otherwise.
+    pub fn next_if(&mut self, f: impl FnOnce(&Event) -> bool) -> io::Result<Option<Event>> {
+        match self.next()? {
+            Some(event) if f(&event) => Ok(Some(event)),
+            event => {
+                self.peeked = event;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn next(&mut self) -> io::Result<Option<Event>> {
+        if let Some(event) = self.peeked.take() {
+            return Ok(Some(event));
+        }
+
+        self.read();
+
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let len = match seq_len(&self.buf) {
+            Some(len) => len,
+            None => {
+                let since = *self.pending.get_or_insert_with(Instant::now);
+                if since.elapsed() < SEQ_TIMEOUT {
+                    return Ok(None);
+                }
+
+                // the sequence never completed, so a lone escape byte is the Esc key
+                // and one followed by a sequence introducer is that key with Alt held
+                self.pending = None;
+                let key = match self.buf.as_slice() {
+                    [b'\x1B'] => Some(Key::Esc),
+                    [b'\x1B', c @ (b'[' | b'O')] => Some(Key::Alt(*c as char)),
+                    _ => None,
+                };
+
+                return Ok(Some(match key {
+                    Some(key) => {
+                        self.buf.clear();
+                        Event::Key(key)
+                    }
+                    None => Event::Unsupported(self.buf.drain(..).collect()),
+                }));
+            }
+        };
+
+        self.pending = None;
+        let seq: Vec<u8> = self.buf.drain(..len).collect();
+
+        Ok(Some(parse(seq)))
+    }
+
+    /// Adds whatever bytes stdin has ready to the buffer, without blocking.
+    fn read(&mut self) {
+        let mut fd = poll_fd(libc::STDIN_FILENO);
+        if unsafe { libc::poll(&mut fd, 1, 0) } <= 0 || fd.revents & libc::POLLIN == 0 {
+            return;
+        }
+
+        let mut chunk = [0u8; 64];
+        let read = unsafe { libc::read(fd.fd, chunk.as_mut_ptr().cast(), chunk.len()) };
+        if read > 0 {
+            self.buf.extend_from_slice(&chunk[..read as usize]);
+        }
+    }
+}
+
+fn poll_fd(fd: libc::c_int) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }
+}
+
+fn parse(seq: Vec<u8>) -> Event {
+    // application cursor mode sends SS3 sequences that termion doesn't know about
+    let key = match seq.as_slice() {
+        b"\x1BOA" => Some(Key::Up),
+        b"\x1BOB" => Some(Key::Down),
+        b"\x1BOC" => Some(Key::Right),
+        b"\x1BOD" => Some(Key::Left),
+        b"\x1BOH" => Some(Key::Home),
+        b"\x1BOF" => Some(Key::End),
+        b"\x1BO" => Some(Key::Alt('O')),
+        _ => None,
+    };
+
+    if let Some(key) = key {
+        return Event::Key(key);
+    }
+
+    // termion only knows motion with the left button held, motion without one comes out as
+    // the same hold so the mouse can be followed either way
+    if let Some(pos) = seq.strip_prefix(b"\x1B[<35;").and_then(|p| p.strip_suffix(b"M")) {
+        let pos = str::from_utf8(pos)
+            .ok()
+            .and_then(|p| p.split_once(';'))
+            .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)));
+        if let Some((x, y)) = pos {
+            return Event::Mouse(MouseEvent::Hold(x, y));
+        }
+    }
+
+    // termion unwraps the parameters of `CSI n ~` sequences as u8
+    if seq.starts_with(b"\x1B[") && seq.ends_with(b"~") {
+        let params = &seq[2..seq.len() - 1];
+        let valid = params.split(|b| *b == b';').all(|n| {
+            str::from_utf8(n)
+                .ok()
+                .and_then(|n| n.parse::<u8>().ok())
+                .is_some()
+        });
+
+        if !valid {
+            return Event::Unsupported(seq);
+        }
+    }
+
+    let mut iter = seq[1..].iter().map(|b| Ok(*b));
+    match parse_event(seq[0], &mut iter) {
+        Ok(event) => event,
+        Err(_) => Event::Unsupported(seq),
+    }
+}
+
+/// Length of the first complete sequence in `buf`, or `None` if more bytes are needed.
+fn seq_len(buf: &[u8]) -> Option<usize> {
+    match buf[0] {
+        b'\x1B' => match buf.get(1)? {
+            b'\x1B' => Some(1),
+            // SS3 finals are uppercase, anything else after ESC O is Alt-O and a new key
+            b'O' => match buf.get(2)? {
+                b'A'..=b'Z' => Some(3),
+                _ => Some(2),
+            },
+            b'[' => csi_len(buf),
+            c => complete(buf, 1 + utf8_len(*c)),
+        },
+        c => complete(buf, utf8_len(c)),
+    }
+}
+
+fn csi_len(buf: &[u8]) -> Option<usize> {
+    match buf.get(2)? {
+        // X10 mouse encoding: ESC [ M Cb Cx Cy
+        b'M' => complete(buf, 6),
+        // linux console function keys: ESC [ [ A
+        b'[' => complete(buf, 4),
+        // xterm mouse encoding: ESC [ < Cb ; Cx ; Cy (M or m)
+        b'<' => buf[3..]
+            .iter()
+            .position(|b| matches!(b, b'M' | b'm'))
+            .map(|i| i + 4),
+        // everything else runs until a final byte in 64..=126
+        _ => buf[2..]
+            .iter()
+            .position(|b| (64..=126).contains(b))
+            .map(|i| i + 3),
+    }
+}
+
+fn complete(buf: &[u8], len: usize) -> Option<usize> {
+    (buf.len() >= len).then_some(len)
+}
+
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0xF0..=0xFF => 4,
+        0xE0..=0xEF => 3,
+        0xC0..=0xDF => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alt_keys_are_one_sequence() {
+        assert_eq!(seq_len(b"\x1Bv"), Some(2));
+        assert_eq!(parse(b"\x1Bv".to_vec()), Event::Key(Key::Alt('v')));
+        assert_eq!(seq_len(b"\x1Bvj"), Some(2));
+    }
+
+    #[test]
+    fn lone_escape_waits_for_more() {
+        assert_eq!(seq_len(b"\x1B"), None);
+        // a second escape means the first one was the key on its own
+        assert_eq!(seq_len(b"\x1B\x1B"), Some(1));
+    }
+
+    #[test]
+    fn arrows_are_not_alt_keys() {
+        assert_eq!(parse(b"\x1B[A".to_vec()), Event::Key(Key::Up));
+        assert_eq!(parse(b"\x1BOA".to_vec()), Event::Key(Key::Up));
+    }
+}
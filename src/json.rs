@@ -0,0 +1,304 @@
+use std::{iter::Peekable, str::CharIndices};
+
+// arrays and objects inside each other at most, the parser recurses for every one of them
+const MAX_DEPTH: usize = 512;
+
+/// A parsed JSON value, numbers are kept as written so large integers don't lose precision.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    // fields in the order they were written, duplicates included
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// What kind of value this is, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+}
+
+/// Parses `text` as a single JSON value, errors say where in it the problem is.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text,
+        chars: text.char_indices().peekable(),
+        depth: 0,
+    };
+
+    let value = parser.value()?;
+    parser.skip_space();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("trailing characters after the value")),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    // arrays and objects the parser is inside of
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_space();
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some(c @ ('{' | '[')) => {
+                // deep enough nesting would overflow the stack instead
+                if self.depth == MAX_DEPTH {
+                    let message = format!("more than {} nested arrays and objects", MAX_DEPTH);
+                    return Err(self.error(&message));
+                }
+                self.depth += 1;
+                let value = match c {
+                    '{' => self.object(),
+                    _ => self.array(),
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('n') => self.literal("null", Value::Null),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.chars.next();
+        let mut fields = Vec::new();
+
+        self.skip_space();
+        if self.eat('}') {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            if self.chars.peek().map(|(_, c)| *c) != Some('"') {
+                return Err(self.error("expected a field name"));
+            }
+            let key = self.string()?;
+            self.skip_space();
+            if !self.eat(':') {
+                return Err(self.error("expected ':' after the field name"));
+            }
+            fields.push((key, self.value()?));
+
+            self.skip_space();
+            match self.chars.next().map(|(_, c)| c) {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(fields)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.chars.next();
+        let mut items = Vec::new();
+
+        self.skip_space();
+        if self.eat(']') {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+
+            self.skip_space();
+            match self.chars.next().map(|(_, c)| c) {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut out = String::new();
+
+        loop {
+            let c = match self.chars.next() {
+                Some((_, c)) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            match c {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                c if c < ' ' => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.chars.next() {
+            Some((_, c)) => c,
+            None => return Err(self.error("unterminated string")),
+        };
+        match c {
+            '"' | '\\' | '/' => Ok(c),
+            'b' => Ok('\u{8}'),
+            'f' => Ok('\u{c}'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            'u' => {
+                let high = self.hex4()?;
+                // characters outside the basic plane come as a pair of surrogates
+                let code = match high {
+                    0xD800..=0xDBFF => {
+                        if !(self.eat('\\') && self.eat('u')) {
+                            return Err(self.error("unpaired surrogate in string"));
+                        }
+                        let low = self.hex4()?;
+                        if !(0xDC00..=0xDFFF).contains(&low) {
+                            return Err(self.error("unpaired surrogate in string"));
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    code => code,
+                };
+                char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate in string"))
+            }
+            c => Err(self.error(&format!("unknown escape '\\{}'", c))),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| self.error("expected four hex digits after '\\u'"))?;
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.offset();
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .is_some()
+        {}
+        let text = &self.text[start..self.offset()];
+
+        // the grammar is stricter than what the loop takes, a float parse checks the rest
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let leading_zero =
+            digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_ascii_digit());
+        let bare = !digits.starts_with(|c: char| c.is_ascii_digit());
+        if leading_zero || bare || text.ends_with('.') || text.parse::<f64>().is_err() {
+            return Err(self.error(&format!("invalid number '{}'", text)));
+        }
+
+        Ok(Value::Number(text.to_string()))
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        let start = self.offset();
+        if !self.text[start..].starts_with(word) {
+            return Err(self.error("unexpected word"));
+        }
+        for _ in word.chars() {
+            self.chars.next();
+        }
+
+        Ok(value)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.chars.next_if(|(_, next)| *next == c).is_some()
+    }
+
+    fn skip_space(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |(i, _)| *i)
+    }
+
+    /// `message` with the line and column the parser has got to.
+    fn error(&mut self, message: &str) -> String {
+        let before = &self.text[..self.offset()];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+        format!("{} at line {} column {}", message, line, column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values() {
+        let value = parse(r#"{"a": [1, "two", null], "b": {"c": true}}"#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                (
+                    "a".to_string(),
+                    Value::Array(vec![
+                        Value::Number("1".to_string()),
+                        Value::String("two".to_string()),
+                        Value::Null,
+                    ])
+                ),
+                (
+                    "b".to_string(),
+                    Value::Object(vec![("c".to_string(), Value::Bool(true))])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse(&deep).is_ok());
+
+        let deeper = format!("{}{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1));
+        let error = parse(&deeper).unwrap_err();
+        assert!(error.starts_with("more than 512 nested"), "{}", error);
+
+        // far past the limit it still fails instead of overflowing the stack
+        assert!(parse(&"[".repeat(200_000)).is_err());
+        assert!(parse(&r#"{"a":"#.repeat(200_000)).is_err());
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(
+            parse("[1,\n 2,]").unwrap_err(),
+            "unexpected ']' at line 2 column 4"
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use termion::event::Key;
+
+/// Built-in sets of bindings the config file adjusts.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Vim,
+    // the vim bindings plus emacs movement, search and cancel keys
+    Emacs,
+}
+
+/// Everything a key can be bound to in normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveDown,
+    MoveUp,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    First,
+    Last,
+    ScrollCenter,
+    ScrollTop,
+    ScrollBottom,
+    Command,
+    Search,
+    NextMatch,
+    PrevMatch,
+    Filter,
+    Sort,
+    ReverseSort,
+    Toggle,
+    ToggleAdvance,
+    Visual,
+    SelectAll,
+    ClearSelection,
+    Invert,
+    NextSelected,
+    PrevSelected,
+    CopyHash,
+    Detail,
+    ToggleBytes,
+    ToggleHash,
+    LineNumbers,
+    HideLocal,
+    SetMark,
+    JumpMark,
+    Undo,
+    Redo,
+    Refresh,
+    Suspend,
+    Cancel,
+    Help,
+    Confirm,
+    Quit,
+}
+
+/// Actions the footer hints at with a short label, in groups that each get a line.
+pub const HINTS: [(&str, &[(Action, &str)]); 3] = [
+    (
+        "move",
+        &[
+            (Action::MoveDown, "down"),
+            (Action::MoveUp, "up"),
+            (Action::First, "top"),
+            (Action::Last, "bottom"),
+            (Action::PageDown, "page"),
+            (Action::Search, "search"),
+            (Action::Filter, "filter"),
+        ],
+    ),
+    (
+        "select",
+        &[
+            (Action::Toggle, "toggle"),
+            (Action::Visual, "range"),
+            (Action::SelectAll, "all"),
+            (Action::Invert, "invert"),
+            (Action::ClearSelection, "none"),
+            (Action::Undo, "undo"),
+        ],
+    ),
+    (
+        "act",
+        &[
+            (Action::Confirm, "download"),
+            (Action::Sort, "sort"),
+            (Action::Detail, "details"),
+            (Action::Help, "help"),
+            (Action::Quit, "quit"),
+        ],
+    ),
+];
+
+impl Action {
+    // in the order they are listed in the help overlay
+    pub const ALL: &'static [Action] = &[
+        Action::MoveDown,
+        Action::MoveUp,
+        Action::PageDown,
+        Action::PageUp,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::First,
+        Action::Last,
+        Action::ScrollCenter,
+        Action::ScrollTop,
+        Action::ScrollBottom,
+        Action::Command,
+        Action::Search,
+        Action::NextMatch,
+        Action::PrevMatch,
+        Action::Filter,
+        Action::Sort,
+        Action::ReverseSort,
+        Action::Toggle,
+        Action::ToggleAdvance,
+        Action::Visual,
+        Action::SelectAll,
+        Action::ClearSelection,
+        Action::Invert,
+        Action::NextSelected,
+        Action::PrevSelected,
+        Action::CopyHash,
+        Action::Detail,
+        Action::ToggleBytes,
+        Action::ToggleHash,
+        Action::LineNumbers,
+        Action::HideLocal,
+        Action::SetMark,
+        Action::JumpMark,
+        Action::Undo,
+        Action::Redo,
+        Action::Refresh,
+        Action::Suspend,
+        Action::Cancel,
+        Action::Help,
+        Action::Confirm,
+        Action::Quit,
+    ];
+
+    /// Name used for the action in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveDown => "move_down",
+            Action::MoveUp => "move_up",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::HalfPageDown => "half_page_down",
+            Action::HalfPageUp => "half_page_up",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::ScrollCenter => "scroll_center",
+            Action::ScrollTop => "scroll_top",
+            Action::ScrollBottom => "scroll_bottom",
+            Action::Command => "command",
+            Action::Search => "search",
+            Action::NextMatch => "next_match",
+            Action::PrevMatch => "prev_match",
+            Action::Filter => "filter",
+            Action::Sort => "sort",
+            Action::ReverseSort => "reverse_sort",
+            Action::Toggle => "toggle",
+            Action::ToggleAdvance => "toggle_advance",
+            Action::Visual => "visual",
+            Action::SelectAll => "select_all",
+            Action::ClearSelection => "clear_selection",
+            Action::Invert => "invert",
+            Action::NextSelected => "next_selected",
+            Action::PrevSelected => "prev_selected",
+            Action::CopyHash => "copy_hash",
+            Action::Detail => "detail",
+            Action::ToggleBytes => "toggle_bytes",
+            Action::ToggleHash => "toggle_hash",
+            Action::LineNumbers => "line_numbers",
+            Action::HideLocal => "hide_local",
+            Action::SetMark => "set_mark",
+            Action::JumpMark => "jump_mark",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Refresh => "refresh",
+            Action::Suspend => "suspend",
+            Action::Cancel => "cancel",
+            Action::Help => "help",
+            Action::Confirm => "confirm",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// Whether the key typed after the binding is passed along, like the letter of `ma`.
+    pub fn takes_key(self) -> bool {
+        matches!(self, Action::SetMark | Action::JumpMark)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::MoveDown => "move down",
+            Action::MoveUp => "move up",
+            Action::PageDown => "move a page down",
+            Action::PageUp => "move a page up",
+            Action::HalfPageDown => "move half a page down",
+            Action::HalfPageUp => "move half a page up",
+            Action::First => "first entry, or entry [count]",
+            Action::Last => "last entry, or entry [count]",
+            Action::ScrollCenter => "scroll the entry to the middle",
+            Action::ScrollTop => "scroll the entry to the top",
+            Action::ScrollBottom => "scroll the entry to the bottom",
+            Action::Command => {
+                "command: N, sort, filter, select, deselect, clear, dedupe, columns, q"
+            }
+            Action::Search => "search names",
+            Action::NextMatch => "next match",
+            Action::PrevMatch => "previous match",
+            Action::Filter => "filter the list",
+            Action::Sort => "sort by name, size, hash, modified time or type",
+            Action::ReverseSort => "reverse the sort order",
+            Action::Toggle => "toggle selection",
+            Action::ToggleAdvance => "toggle and move down",
+            Action::Visual => "select a range",
+            Action::SelectAll => "select all",
+            Action::ClearSelection => "clear selection",
+            Action::Invert => "invert selection",
+            Action::NextSelected => "next selected entry",
+            Action::PrevSelected => "previous selected entry",
+            Action::CopyHash => "copy the full hash",
+            Action::Detail => "show entry details",
+            Action::ToggleBytes => "switch between readable sizes and bytes",
+            Action::ToggleHash => "switch between short and full hashes",
+            Action::LineNumbers => "show or hide line numbers",
+            Action::HideLocal => "hide or show files that are already downloaded",
+            Action::SetMark => "mark the entry with the next letter",
+            Action::JumpMark => "jump to the entry marked with the next letter",
+            Action::Undo => "undo a selection change",
+            Action::Redo => "redo a selection change",
+            Action::Refresh => "reload the file listing",
+            Action::Suspend => "suspend to the shell",
+            Action::Cancel => "cancel",
+            Action::Help => "show this help",
+            Action::Confirm => "download selected files, or jump to entry [count]",
+            Action::Quit => "quit",
+        }
+    }
+
+    fn default_keys(self) -> Vec<Key> {
+        match self {
+            Action::MoveDown => vec![Key::Char('j'), Key::Down],
+            Action::MoveUp => vec![Key::Char('k'), Key::Up],
+            Action::PageDown => vec![Key::PageDown, Key::Ctrl('f')],
+            Action::PageUp => vec![Key::PageUp, Key::Ctrl('b')],
+            Action::HalfPageDown => vec![Key::Ctrl('d')],
+            Action::HalfPageUp => vec![Key::Ctrl('u')],
+            Action::First => vec![Key::Home],
+            Action::Last => vec![Key::Char('G'), Key::End],
+            Action::ScrollCenter | Action::ScrollTop | Action::ScrollBottom => Vec::new(),
+            Action::Command => vec![Key::Char(':')],
+            Action::Search => vec![Key::Char('/')],
+            Action::NextMatch => vec![Key::Char('n')],
+            Action::PrevMatch => vec![Key::Char('N')],
+            Action::Filter => vec![Key::Char('f')],
+            Action::Sort => vec![Key::Char('s')],
+            Action::ReverseSort => vec![Key::Char('S')],
+            Action::Toggle => vec![Key::Char(' ')],
+            Action::ToggleAdvance => vec![Key::Char('X')],
+            Action::Visual => vec![Key::Char('v')],
+            Action::SelectAll => vec![Key::Char('a')],
+            Action::ClearSelection => vec![Key::Char('A'), Key::Char('c')],
+            Action::Invert => vec![Key::Char('i')],
+            Action::NextSelected => vec![Key::Char(']')],
+            Action::PrevSelected => vec![Key::Char('[')],
+            Action::CopyHash => vec![Key::Char('y')],
+            Action::Detail => vec![Key::Char('d')],
+            Action::ToggleBytes => vec![Key::Char('t')],
+            Action::ToggleHash => vec![Key::Char('h')],
+            Action::LineNumbers => vec![Key::Char('#')],
+            Action::HideLocal => vec![Key::Char('D')],
+            Action::SetMark => vec![Key::Char('m')],
+            Action::JumpMark => vec![Key::Char('\''), Key::Char('`')],
+            Action::Undo => vec![Key::Char('u')],
+            Action::Redo => vec![Key::Ctrl('r')],
+            Action::Refresh => vec![Key::Char('r')],
+            Action::Suspend => vec![Key::Ctrl('z')],
+            Action::Cancel => vec![Key::Esc],
+            Action::Help => vec![Key::Char('?')],
+            Action::Confirm => vec![Key::Char('\n')],
+            Action::Quit => vec![Key::Char('q'), Key::Ctrl('c')],
+        }
+    }
+
+    fn default_bindings(self) -> Vec<Vec<Key>> {
+        let mut bindings: Vec<Vec<Key>> =
+            self.default_keys().into_iter().map(|k| vec![k]).collect();
+        let sequence = match self {
+            Action::First => Some(['g', 'g']),
+            Action::ScrollCenter => Some(['z', 'z']),
+            Action::ScrollTop => Some(['z', 't']),
+            Action::ScrollBottom => Some(['z', 'b']),
+            _ => None,
+        };
+        if let Some(keys) = sequence {
+            bindings.insert(0, keys.into_iter().map(Key::Char).collect());
+        }
+
+        bindings
+    }
+
+    fn emacs_keys(self) -> Vec<Key> {
+        match self {
+            Action::MoveDown => vec![Key::Ctrl('n')],
+            Action::MoveUp => vec![Key::Ctrl('p')],
+            Action::PageDown => vec![Key::Ctrl('v')],
+            Action::PageUp => vec![Key::Alt('v')],
+            Action::First => vec![Key::Alt('<')],
+            Action::Last => vec![Key::Alt('>')],
+            Action::ScrollCenter => vec![Key::Ctrl('l')],
+            Action::Search => vec![Key::Ctrl('s')],
+            Action::Cancel => vec![Key::Ctrl('g')],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// What a sequence of keys typed so far leads to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    // an action along with the key typed after its binding
+    Argument(Action, Key),
+    // the start of a longer binding, more keys have to follow
+    Prefix,
+    None,
+}
+
+/// Key sequences bound to each action, in help order.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<Vec<Key>>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(Profile::default())
+    }
+}
+
+impl Keymap {
+    pub fn new(profile: Profile) -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|a| {
+                let emacs = a.emacs_keys().into_iter().map(|k| vec![k]);
+                let bindings = match profile {
+                    Profile::Vim => a.default_bindings(),
+                    // the emacs keys come first so the help overlay leads with them
+                    Profile::Emacs => emacs.chain(a.default_bindings()).collect(),
+                };
+                (*a, bindings)
+            })
+            .collect();
+
+        Self { bindings }
+    }
+
+    /// Action bound to a single key.
+    pub fn get(&self, key: Key) -> Option<Action> {
+        match self.lookup(&[key]) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut prefix = false;
+        for (action, bindings) in &self.bindings {
+            for binding in bindings {
+                if action.takes_key() {
+                    match keys.split_last() {
+                        Some((key, rest)) if rest == binding.as_slice() => {
+                            return Lookup::Argument(*action, *key)
+                        }
+                        _ if binding == keys => return Lookup::Prefix,
+                        _ => {}
+                    }
+                }
+                if binding == keys {
+                    return Lookup::Action(*action);
+                }
+                prefix |= binding.starts_with(keys);
+            }
+        }
+
+        match prefix {
+            true => Lookup::Prefix,
+            false => Lookup::None,
+        }
+    }
+
+    pub fn keys(&self, action: Action) -> &[Vec<Key>] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, bindings)| bindings.as_slice())
+    }
+
+    /// Replaces the bindings of `action`, taking away any of another action that would clash.
+    pub fn bind(&mut self, action: Action, bindings: Vec<Vec<Key>>) {
+        for (a, bound) in &mut self.bindings {
+            match *a == action {
+                true => *bound = bindings.clone(),
+                false => bound.retain(|b| !bindings.iter().any(|new| clashes(b, new))),
+            }
+        }
+    }
+
+    /// Keys of `action` as shown in the help overlay and the footer.
+    pub fn describe_keys(&self, action: Action) -> String {
+        let names: Vec<String> = self.keys(action).iter().map(|b| sequence_name(b)).collect();
+        names.join(" ")
+    }
+}
+
+/// Whether one binding equals or starts the other, so only one of them could ever fire.
+pub fn clashes(a: &[Key], b: &[Key]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// Parses a binding of keys separated by spaces like `g g`, see `parse_key`.
+pub fn parse_sequence(s: &str) -> Result<Vec<Key>, String> {
+    let keys = s
+        .split_whitespace()
+        .map(|k| parse_key(k).ok_or_else(|| format!("unknown key '{}'", k)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match keys.is_empty() {
+        true => Err("empty key binding".to_string()),
+        false => Ok(keys),
+    }
+}
+
+pub fn sequence_name(keys: &[Key]) -> String {
+    // plain characters run together like vim's `gg`
+    let sep = match keys
+        .iter()
+        .all(|k| matches!(k, Key::Char(c) if c.is_ascii_graphic()))
+    {
+        true => "",
+        false => " ",
+    };
+    let names: Vec<String> = keys.iter().map(|k| key_name(*k)).collect();
+    names.join(sep)
+}
+
+/// Parses a key like `j`, `Space`, `PageDown`, `C-f` or `M-v`.
+pub fn parse_key(s: &str) -> Option<Key> {
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+
+    if let Some(rest) = ["C-", "Ctrl-", "^"].iter().find_map(|p| s.strip_prefix(p)) {
+        return single(rest).map(|c| Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(rest) = ["M-", "Alt-"].iter().find_map(|p| s.strip_prefix(p)) {
+        return single(rest).map(Key::Alt);
+    }
+    if let Some(n) = s.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=12).contains(&n).then_some(Key::F(n));
+    }
+
+    let key = match s {
+        "Space" => Key::Char(' '),
+        "Enter" => Key::Char('\n'),
+        "Tab" => Key::Char('\t'),
+        "Esc" => Key::Esc,
+        "Backspace" => Key::Backspace,
+        "Delete" => Key::Delete,
+        "Insert" => Key::Insert,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" | "PgUp" => Key::PageUp,
+        "PageDown" | "PgDn" => Key::PageDown,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("^{}", c),
+        Key::Alt(c) => format!("M-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Esc => "Esc".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PgUp".to_string(),
+        Key::PageDown => "PgDn".to_string(),
+        _ => format!("{:?}", key),
+    }
+}
+
+fn single(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what typing `binding` ends up doing, with a mark letter after it for the actions that
+    // take one
+    fn fires(keymap: &Keymap, binding: &[Key]) -> Option<Action> {
+        let with_arg = [binding, &[Key::Char('a')]].concat();
+        match (keymap.lookup(binding), keymap.lookup(&with_arg)) {
+            (Lookup::Action(action), _) => Some(action),
+            (Lookup::Prefix, Lookup::Argument(action, Key::Char('a'))) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn every_action_is_reachable() {
+        for profile in [Profile::Vim, Profile::Emacs] {
+            let keymap = Keymap::new(profile);
+            for action in Action::ALL {
+                let reachable = keymap
+                    .keys(*action)
+                    .iter()
+                    .any(|b| fires(&keymap, b) == Some(*action));
+                assert!(reachable, "{:?} unreachable in {:?}", action, profile);
+            }
+        }
+    }
+
+    #[test]
+    fn emacs_keys() {
+        let keymap = Keymap::new(Profile::Emacs);
+        assert_eq!(keymap.get(Key::Ctrl('n')), Some(Action::MoveDown));
+        assert_eq!(keymap.get(Key::Ctrl('p')), Some(Action::MoveUp));
+        assert_eq!(keymap.get(Key::Ctrl('v')), Some(Action::PageDown));
+        assert_eq!(keymap.get(Key::Alt('v')), Some(Action::PageUp));
+        assert_eq!(keymap.get(Key::Ctrl('g')), Some(Action::Cancel));
+        assert_eq!(keymap.get(Key::Ctrl('s')), Some(Action::Search));
+        // the vim keys are still there
+        assert_eq!(keymap.get(Key::Char('j')), Some(Action::MoveDown));
+        assert_eq!(keymap.get(Key::Esc), Some(Action::Cancel));
+    }
+
+    #[test]
+    fn vim_leaves_emacs_keys_unbound() {
+        let keymap = Keymap::new(Profile::Vim);
+        assert_eq!(keymap.get(Key::Ctrl('n')), None);
+        assert_eq!(keymap.get(Key::Alt('v')), None);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(*action));
+        }
+    }
+}
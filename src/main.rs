@@ -1,40 +1,289 @@
+mod config;
+mod csv;
+mod date;
+mod filetype;
+#[cfg(feature = "url")]
+mod http;
+mod input;
+mod json;
+mod keymap;
+mod pattern;
+mod screen;
+mod sha256;
+mod size;
+mod terminal;
+mod theme;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{Parser, ValueEnum};
+use config::{Config, Numbering};
+use input::Input;
+use keymap::{Action, Keymap, Lookup, Profile};
 use rand::{
     distributions::{Alphanumeric, DistString},
     Rng,
 };
-use signal_hook::{consts::SIGWINCH, iterator::Signals};
+use screen::Screen;
+use signal_hook::{
+    consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP, SIGWINCH},
+    iterator::Signals,
+    low_level,
+};
+use size::Units;
 use std::{
-    cmp::max,
-    collections::HashMap,
+    cmp::{max, min},
+    collections::{BTreeMap, HashMap, VecDeque},
+    env,
     error::Error,
-    io::{stdout, Read, StdoutLock, Write},
-    sync::mpsc::{self, Receiver, Sender},
+    fs::{self, File},
+    io::{stdin, stdout, Read, Write},
+    mem,
+    ops::RangeInclusive,
+    panic,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
     thread::{self},
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use termion::{
-    async_stdin, clear,
-    color::{self, Bg, Fg},
-    cursor,
-    event::{parse_event, Event, Key},
-    raw::{IntoRawMode, RawTerminal},
-    screen::{AlternateScreen, IntoAlternateScreen},
-    style, terminal_size,
+    clear, cursor,
+    event::{Event, Key, MouseButton, MouseEvent},
+    terminal_size,
 };
+use theme::{ColorMode, Depth, Theme};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-type RawOut<'a> = AlternateScreen<RawTerminal<StdoutLock<'a>>>;
+// raw mode, the alternate screen and mouse reporting are handled by the terminal module
+type RawOut<'a> = Screen<Box<dyn Write + 'a>>;
+// the file listing, or why it couldn't be had
+type Listing = Result<BTreeMap<String, Entry>, String>;
 
 const BORDER: (u16, u16) = (10, 2);
 
+// a line of key hints for each group, shown while the list keeps at least the minimum of rows
+const HINT_LINES: u16 = keymap::HINTS.len() as u16;
+const MIN_HINT_PAGE: u16 = 8;
+// the size assumed without a terminal to ask, like in tests
+const FALLBACK_SIZE: (u16, u16) = (80, 24);
+// the side pane only opens on terminals this wide, and takes a third of them up to the max
+const PANE_MIN_TERMINAL: u16 = 120;
+const MAX_PANE_WIDTH: u16 = 60;
+
+const TITLE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
 const COL_SEPARATOR: &str = "        ";
 const COL_SPACING: u16 = COL_SEPARATOR.len() as u16;
+// names are never cut shorter than this, however narrow the terminal
+const MIN_NAME_WIDTH: usize = 8;
+// a listing without any files still gets a table wide enough for the footer
+const EMPTY_WIDTH: usize = 50;
+// room for a full progress bar like "[##########] 100%"
+const STATUS_WIDTH: usize = 17;
+// a narrow terminal leaves the status just enough for "queued" and "failed"
+const MIN_STATUS_WIDTH: usize = 6;
+// the overall progress bar in the footer grows with the terminal between these
+const MIN_BAR_WIDTH: usize = 10;
+const MAX_BAR_WIDTH: usize = 30;
+
+// how long the loop waits for input when nothing is due, threads with news wake it sooner
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+// how often the download spinner moves, and the download's progress is picked up
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const ASCII_SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+// progress is sampled this often for the speed, and the last few samples are averaged
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+const RATE_SAMPLES: usize = 10;
+// samples needed before there's an estimate at all
+const MIN_RATE_SAMPLES: usize = 3;
+
+// how long a notice stays up if no key is pressed
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(4);
+
+// messages starting with this are drawn in the error color
+const ERROR_PREFIX: &str = "error: ";
+
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+// resizes that come in closer together than this are drawn as one
+const RESIZE_DELAY: Duration = Duration::from_millis(50);
+// how long a started key sequence like `gg` waits for its next key
+const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// selection changes that can be undone
+const MAX_UNDO: usize = 50;
+
+const MAX_COUNT: usize = 99999;
+// characters of a whole SHA-256 in hex
+const FULL_HASH: usize = 64;
+
+// what the hash column shows while a local file is still being hashed
+const PENDING_HASH: &str = "…";
+// threads hashing the files of a local directory at most
+const MAX_HASHERS: usize = 4;
+// how much the mock download sends of a file the listing has no size for
+const MOCK_UNKNOWN_SIZE: u64 = 256 * 1024;
+const COUNT_WIDTH: usize = 5;
+
+/// Terminal picker for selecting files to download
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Directory to list and hash the files of, instead of the sample listing
+    #[arg(value_name = "DIR", conflicts_with_all = ["input", "stdin_format"])]
+    #[cfg_attr(feature = "url", arg(conflicts_with = "url"))]
+    dir: Option<PathBuf>,
+
+    /// Fetch the listing, in the JSON format of --stdin-format, from a plain http:// URL.
+    /// https:// URLs are turned down, there's no TLS support
+    #[cfg(feature = "url")]
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = http::Url::parse,
+        conflicts_with_all = ["input", "stdin_format"]
+    )]
+    url: Option<http::Url>,
+
+    /// Wrap the pointer around when moving past the first or last entry
+    #[arg(long)]
+    wrap: bool,
+
+    /// Show file sizes in exact bytes instead of KiB, MiB and so on
+    #[arg(long)]
+    bytes: bool,
+
+    /// Show the whole SHA-256 of each file instead of its first characters, if it fits
+    #[arg(long)]
+    full_hash: bool,
+
+    /// Draw on the main screen and leave the table and a summary in the scrollback on exit
+    #[arg(long)]
+    no_alt_screen: bool,
+
+    /// Use plain ASCII for the checkboxes, the sort direction, the scrollbar and the frame
+    #[arg(long)]
+    ascii: bool,
+
+    /// Draw without colors or text styles, like setting NO_COLOR or --color=never
+    #[arg(long, conflicts_with = "color")]
+    no_color: bool,
+
+    /// When to use colors, auto picks as many as $TERM and $COLORTERM say the terminal has
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Characters of each hash to show, 0 hides the column, overriding the config file
+    #[arg(long, value_name = "N")]
+    hash_chars: Option<usize>,
+
+    /// Built-in key bindings to start from, overriding the config file
+    #[arg(long, value_enum, value_name = "PROFILE")]
+    keys: Option<Profile>,
+
+    /// Prefix file types with Nerd Font icons
+    #[arg(long)]
+    icons: bool,
+
+    /// Address of the server the listing comes from
+    #[arg(long, value_name = "HOST:PORT", value_parser = server_addr)]
+    server: Option<String>,
+
+    /// Make every tenth file of the mock download fail and the mock server drop its connection,
+    /// to see how failures are shown
+    #[arg(long)]
+    mock_faults: bool,
+
+    /// Directory files are downloaded to, entries already there are dimmed
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    dest: PathBuf,
+
+    /// Columns to show out of name, type, size, hash and modified, separated by commas
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = column_name)]
+    columns: Option<Vec<SortKey>>,
+
+    /// Read the listing from stdin in this format instead of using the sample one
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "input")]
+    stdin_format: Option<StdinFormat>,
+
+    /// Read the listing from a CSV or TSV file of name, size and hash columns
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Field delimiter of the --input file, a single character or "tab", guessed if not set
+    #[arg(long, value_name = "CHAR", value_parser = delimiter, requires = "input")]
+    delimiter: Option<char>,
+
+    /// Only list files whose names match this glob, may be given more than once
+    #[arg(long, value_name = "GLOB")]
+    only: Vec<String>,
 
-const HEADER_COLOR: Fg<color::LightGreen> = Fg(color::LightGreen);
-const TITLE_COLOR: Fg<color::White> = Fg(color::White);
-const LIST_COLOR: Fg<color::LightYellow> = Fg(color::LightYellow);
-const POINTER_FG_COLOR: Fg<color::White> = Fg(color::White);
-const POINTER_BG_COLOR: Bg<color::LightBlack> = Bg(color::LightBlack);
-const FOOTER_COLOR: Fg<color::LightBlue> = Fg(color::LightBlue);
+    /// Leave out files whose names match this glob, may be given more than once
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+/// Formats of a listing piped in on stdin.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum StdinFormat {
+    // an array of objects with name, size, and optionally hash and mtime
+    Json,
+}
+
+/// Where the listing comes from, it's read again on every refresh.
+#[derive(Debug, Clone)]
+enum Source {
+    // random files to try things out with
+    Sample,
+    // piped in with --stdin-format, which a refresh can only show again
+    Stdin(BTreeMap<String, Entry>),
+    // the file given with --input and its delimiter if set
+    Input(PathBuf, Option<char>),
+    // a directory given on the command line
+    Dir(PathBuf),
+    // fetched with --url
+    #[cfg(feature = "url")]
+    Url(http::Url),
+}
+
+impl Source {
+    fn read(&self) -> Listing {
+        match self {
+            Source::Sample => mock_listing(),
+            Source::Stdin(data) => Ok(data.clone()),
+            Source::Input(path, delimiter) => input_listing(path, *delimiter),
+            Source::Dir(dir) => local_listing(dir),
+            #[cfg(feature = "url")]
+            Source::Url(url) => url_listing(url),
+        }
+    }
+
+    /// What the listing is, given how long reading it took.
+    #[cfg_attr(not(feature = "url"), allow(unused_variables))]
+    fn describe(&self, took: Duration) -> String {
+        match self {
+            Source::Sample => "Showing the built-in sample listing".to_string(),
+            Source::Stdin(_) => "Showing the listing from stdin".to_string(),
+            Source::Input(path, _) => format!("Showing {}", path.display()),
+            Source::Dir(dir) => format!("Directory {}", dir.display()),
+            #[cfg(feature = "url")]
+            Source::Url(url) => format!("Fetched from {} in {} ms", url.host, took.as_millis()),
+        }
+    }
+}
+
+// what the signal thread tells the main loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    Resize,
+    Resume,
+}
 
 #[derive(Debug, Clone, Copy)]
 enum Direction {
@@ -42,81 +291,684 @@ enum Direction {
     Down,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Normal,
+    // anchor index of the range being marked
+    Visual(usize),
+    // text typed after ':'
+    Command(String),
+    // query typed after '/' and the index the search started from
+    Search(String, usize),
+    // fuzzy filter being edited after 'f'
+    Filter(String),
+    Help,
+    // full name, size and hash of the entry under the pointer
+    Detail,
+    // asking whether to quit and abandon the running download
+    ConfirmQuit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Name,
+    Type,
+    Size,
+    Hash,
+    Modified,
+}
+
+impl SortKey {
+    // in the order the columns are drawn
+    const ALL: [SortKey; 5] = [
+        SortKey::Name,
+        SortKey::Type,
+        SortKey::Size,
+        SortKey::Hash,
+        SortKey::Modified,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(SortKey::Name),
+            "type" => Some(SortKey::Type),
+            "size" => Some(SortKey::Size),
+            "hash" => Some(SortKey::Hash),
+            "modified" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            SortKey::Name => "Name",
+            SortKey::Type => "Type",
+            SortKey::Size => "Size",
+            SortKey::Hash => "SHA-256",
+            SortKey::Modified => "Modified",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Hash,
+            SortKey::Hash => SortKey::Modified,
+            SortKey::Modified => SortKey::Type,
+            SortKey::Type => SortKey::Name,
+        }
+    }
+}
+
+/// Cells of each column, 0 for one that isn't shown.
+#[derive(Debug, Clone, Copy, Default)]
+struct Widths {
+    name: usize,
+    kind: usize,
+    size: usize,
+    hash: usize,
+    time: usize,
+    status: usize,
+}
+
+impl Widths {
+    fn column(&mut self, key: SortKey) -> &mut usize {
+        match key {
+            SortKey::Name => &mut self.name,
+            SortKey::Type => &mut self.kind,
+            SortKey::Size => &mut self.size,
+            SortKey::Hash => &mut self.hash,
+            SortKey::Modified => &mut self.time,
+        }
+    }
+
+    fn get(mut self, key: SortKey) -> usize {
+        *self.column(key)
+    }
+}
+
+/// How the cells of each column are written, shared by `widths` and `row_cells`.
+struct Cells<'a> {
+    units: Option<Units>,
+    hash_chars: usize,
+    time_format: &'a str,
+    icons: bool,
+    unicode: bool,
+    // columns turned off with `:columns` or `--columns`
+    hidden: &'a [SortKey],
+    // hashes shared by more than one entry, which get a badge
+    duplicates: &'a HashMap<String, Vec<String>>,
+}
+
+impl Cells<'_> {
+    fn shown(&self, key: SortKey) -> bool {
+        !self.hidden.contains(&key) && (key != SortKey::Hash || self.hash_chars > 0)
+    }
+
+    /// The full text of an entry's cell in column `key`, names aren't cut short here.
+    fn text(&self, key: SortKey, entry: &FileEntry) -> String {
+        match key {
+            SortKey::Name => entry.name.clone(),
+            SortKey::Type => kind_text(&entry.name, self.icons),
+            SortKey::Size => match entry.size {
+                Some(size) => size::format(size, self.units),
+                None => "?".to_string(),
+            },
+            SortKey::Hash => match &entry.error {
+                Some(e) => format!("! {}", e),
+                None if self.duplicates.contains_key(&entry.hash) => {
+                    let badge = match self.unicode {
+                        true => "≡",
+                        false => "=",
+                    };
+                    format!("{} {}", badge, hash_text(&entry.hash, self.hash_chars))
+                }
+                None => hash_text(&entry.hash, self.hash_chars),
+            },
+            SortKey::Modified => time_text(entry.mtime, self.time_format),
+        }
+    }
+}
+
+/// What the listing knows about a file.
+#[derive(Debug, Clone)]
+struct Entry {
+    // bytes, for sources that know it
+    size: Option<u64>,
+    hash: String,
+    // seconds since the epoch, for sources that know it
+    mtime: Option<i64>,
+    // why a file of a local directory couldn't be read or hashed
+    error: Option<String>,
+}
+
+/// Parts of the screen around the table that can be turned on and off.
+#[derive(Debug, Clone, Copy)]
+struct Parts {
+    frame: bool,
+    title: bool,
+    pane: bool,
+    hints: bool,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Layout {
+    // the program name and version, if shown
+    title: Option<(u16, u16)>,
     header: (u16, u16),
     name: (u16, u16),
+    kind: (u16, u16),
     size: (u16, u16),
     hash: (u16, u16),
+    time: (u16, u16),
+    status: (u16, u16),
     list: (u16, u16),
     footer: (u16, u16),
+    // the first line of key hints under the footer, if there's room for them
+    hints: Option<(u16, u16)>,
+    // notices go on their own line under the footer and the hints
+    message: (u16, u16),
+    page: usize,
+    // left, top, right and bottom edges of the frame around the table, if it has one
+    frame: Option<(u16, u16, u16, u16)>,
+    // left edge, top and bottom rows and width of the side pane, while it's open
+    pane: Option<(u16, u16, u16, u16)>,
+    // the size the terminal needs to be when it's too small for all of the above
+    small: Option<(u16, u16)>,
 }
 
 impl Layout {
-    fn new(widths: (usize, usize, usize), n: usize, w: usize, border: (u16, u16)) -> Self {
-        let mid = terminal_size().unwrap().0 / 2;
-        let cent = mid - (w as f32 * 0.5).round() as u16;
+    fn new(
+        widths: Widths,
+        n: usize,
+        w: usize,
+        prefix: u16,
+        border: (u16, u16),
+        parts: Parts,
+        (width, height): (u16, u16),
+    ) -> Self {
+        // cells left of the prefix for the marker and right of the text for the scrollbar,
+        // and one more on each side for the frame
+        let (left, right) = match parts.frame {
+            true => (4, 4),
+            false => (3, 2),
+        };
+        // the table is centered in what the side pane leaves of the width, unless that would
+        // push the prefix off the left edge
+        let pane_w = pane_width(parts.pane, width);
+        let mid = (width - pane_w) / 2;
+        let cent = max(
+            mid.saturating_sub((w as f32 * 0.5).round() as u16),
+            left + prefix,
+        );
+
+        // the title is the first thing to go when rows are short, before the list has to
+        let title = (parts.title && height > border.1 + 9).then(|| {
+            let x = width.saturating_sub(TITLE.width() as u16) / 2 + 1;
+            (x, border.1)
+        });
+        // everything else moves down to make room for it
+        let top = border.1 + title.is_some() as u16;
 
-        let header = (cent, border.1);
-        let name = (cent, border.1 + 3);
-        let size = (name.0 + widths.0 as u16 + COL_SPACING, border.1 + 3);
-        let hash = (size.0 + widths.1 as u16 + COL_SPACING, border.1 + 3);
-        let list = (cent - 4, border.1 + 5);
-        let footer = (cent, border.1 + n as u16 + 7);
+        let header = (cent, top);
+        let name = (cent, top + 3);
+        // dropped columns take no room, the ones after them move over
+        let next = |pos: (u16, u16), w: usize| match w {
+            0 => pos,
+            w => (pos.0 + w as u16 + COL_SPACING, pos.1),
+        };
+        let kind = next(name, widths.name);
+        let size = next(kind, widths.kind);
+        let hash = next(size, widths.size);
+        let time = next(hash, widths.hash);
+        let status = next(time, widths.time);
+        // line numbers and checkboxes stick out to the left of the titles
+        let list = (cent - prefix, top + 5);
+
+        // rows left for the list between the titles and the footer, the hints only take some
+        // of them while plenty are left
+        let free = height.saturating_sub(top + border.1 + 7);
+        let hint_lines = match parts.hints && free >= HINT_LINES + MIN_HINT_PAGE {
+            true => HINT_LINES,
+            false => 0,
+        };
+        let page = max(free - hint_lines, 1) as usize;
+        let footer = (cent, top + min(n, page) as u16 + 7);
+        let hints = (hint_lines > 0).then_some((cent, footer.1 + 1));
+        let message = (cent, footer.1 + 1 + hint_lines);
+
+        // everything has to fit with at least one row of the list, the title aside
+        let need = (left + prefix + w as u16 + right + pane_w, top + 9);
+        let small = (width < need.0 || height < need.1).then_some(need);
+
+        // the rows above the titles and between them and the list are free for the frame, and
+        // sideways it leaves a cell for the marker on the left and the scrollbar on the right
+        let frame = parts.frame.then(|| {
+            (
+                list.0 - 3,
+                name.1 - 1,
+                cent + w as u16 + 4,
+                list.1 + min(n, page) as u16,
+            )
+        });
+        // from the titles down to the bottom border, beside the footer too since long names
+        // and hashes take a few lines
+        let pane = (pane_w > 0).then(|| {
+            (
+                width - pane_w + 1,
+                name.1,
+                height.saturating_sub(border.1),
+                pane_w,
+            )
+        });
 
         Self {
+            title,
             header,
             name,
+            kind,
             size,
             hash,
+            time,
+            status,
             list,
             footer,
+            hints,
+            message,
+            page,
+            frame,
+            pane,
+            small,
+        }
+    }
+}
+
+/// State of the link to the server, as the connection thread reports it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Connection {
+    Connected,
+    Reconnecting,
+    Disconnected,
+}
+
+impl Connection {
+    fn label(self) -> &'static str {
+        match self {
+            Connection::Connected => "connected",
+            Connection::Reconnecting => "reconnecting…",
+            Connection::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// A listed file and what the interface keeps track of for it.
+struct FileEntry {
+    name: String,
+    size: Option<u64>,
+    hash: String,
+    mtime: Option<i64>,
+    error: Option<String>,
+    selected: bool,
+    status: Status,
+    // a file of the same name and size is already in the download directory
+    local: bool,
+}
+
+impl FileEntry {
+    fn new(name: String, entry: Entry) -> Self {
+        Self {
+            name,
+            size: entry.size,
+            hash: entry.hash,
+            mtime: entry.mtime,
+            error: entry.error,
+            selected: false,
+            status: Status::None,
+            local: false,
+        }
+    }
+}
+
+/// Where an entry is in a download.
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    None,
+    Queued,
+    // bytes done out of the size, when the listing has one
+    Transferring(u64, Option<u64>),
+    Done,
+    Failed(String),
+}
+
+impl Status {
+    fn label(&self) -> String {
+        match self {
+            Status::None => String::new(),
+            Status::Queued => "queued".to_string(),
+            Status::Transferring(done, Some(total)) => {
+                format!("{}%", done * 100 / max(*total, 1))
+            }
+            Status::Transferring(done, None) => format!("{} bytes", done),
+            Status::Done => "done".to_string(),
+            Status::Failed(_) => "failed".to_string(),
+        }
+    }
+
+    /// What the status column shows, with a progress bar clipped to `width`.
+    fn cell(&self, width: usize, unicode: bool) -> String {
+        match self {
+            Status::Transferring(done, Some(total)) => {
+                let percent = format!("{}%", done * 100 / max(*total, 1));
+                // brackets and a space around the bar, and room for "100%"
+                let bar = width.saturating_sub(7);
+                if bar == 0 {
+                    return percent;
+                }
+
+                format!("{} {}", progress_bar(*done, *total, bar, '.'), percent)
+            }
+            Status::Done => match unicode {
+                true => "✓".to_string(),
+                false => "ok".to_string(),
+            },
+            status => status.label(),
         }
     }
 }
 
-#[derive(Clone)]
+/// What the download worker reports for each file.
+enum Transfer {
+    Progress(String, u64, Option<u64>),
+    Done(String),
+    Failed(String, String),
+    // the worker itself broke down and won't send anything else
+    Error(String),
+    // every file has been dealt with
+    Finished,
+}
+
+/// A running download worker.
+struct Download {
+    // progress of each file, then `Finished`
+    updates: Receiver<Transfer>,
+    // set to make the worker stop after its current chunk
+    cancel: Arc<AtomicBool>,
+    // how many files were queued and their combined size, leaving out those without one
+    files: usize,
+    total: u64,
+    unknown: bool,
+    // bytes of the finished files that had no size, which their rows don't remember
+    arrived: u64,
+    started: Instant,
+    // spinner frame and when it last moved
+    frame: usize,
+    ticked: Instant,
+    // recent (time, bytes done) readings and the speed they averaged to
+    samples: VecDeque<(Instant, u64)>,
+    rate: Option<f64>,
+}
+
 struct Interface {
     pointer: (u16, u16),
-    data: HashMap<String, (u64, String)>,
-    display: Vec<(String, bool)>,
-    widths: (usize, usize, usize),
+    source: Source,
+    // globs of --only and --exclude, which narrow every listing before it's shown
+    only: Vec<String>,
+    exclude: Vec<String>,
+    // what the listing is, shown above it
+    header: String,
+    server: Option<String>,
+    // where downloads go, and whether what's already there is hidden
+    dest: PathBuf,
+    hide_local: bool,
+    // state changes of the server connection, and the latest one
+    link: Option<Receiver<Connection>>,
+    connection: Option<Connection>,
+    // receives the new listing and how long it took while a refresh is running
+    refresh: Option<Receiver<(Listing, Duration)>>,
+    // digests of a local directory's files as the hashing threads finish them
+    hashes: Option<Receiver<(String, Result<String, String>)>>,
+    // ordered by name, so the entries come out the same way every time
+    files: Vec<FileEntry>,
+    // names sharing each hash listed more than once, worked out when the listing changes
+    duplicates: HashMap<String, Vec<String>>,
+    // all entries in sorted order
+    order: Vec<usize>,
+    // entries currently shown, rows index into this
+    visible: Vec<usize>,
+    filter: Option<String>,
+    sort: Option<SortKey>,
+    reverse: bool,
+    widths: Widths,
+    // the terminal's width and height, as of the last resize
+    size: (u16, u16),
     lay: Layout,
     n: usize,
     w: usize,
     index: usize,
+    // row shown at the top of the list
+    offset: usize,
+    // unit style for sizes, exact bytes while this is toggled off
+    units: Units,
+    bytes: bool,
+    // whether the hash column has the whole digest, only while the table still fits
+    full_hash: bool,
+    // prefix of each hash that is shown, the column is hidden at 0
+    hash_chars: usize,
+    // strftime format of the modified column
+    time_format: String,
+    icons: bool,
+    // columns turned off with `:columns` or `--columns`
+    hidden: Vec<SortKey>,
+    parts: Parts,
+    numbers: bool,
+    numbering: Numbering,
+    // rows kept in view above and below the pointer
+    scrolloff: usize,
+    theme: Theme,
+    // whether the locale can show block characters and arrows
+    unicode: bool,
+    // glyphs for selected and unselected rows
+    checkbox: (String, String),
+    count: Option<usize>,
+    // keys of an unfinished sequence and when the last one was pressed
+    pending: Option<(Vec<Key>, Instant)>,
+    wrap: bool,
+    last_click: Option<(usize, Instant)>,
+    mouse: bool,
+    // screen line of the row the mouse is over
+    hover: Option<u16>,
+    alt_screen: bool,
+    status: Option<String>,
+    message: Option<String>,
+    // the whole of the last error, which the message line may have cut short
+    last_error: Option<String>,
+    // the message on screen and since when, so a new one starts its own timeout
+    shown: Option<(String, Instant)>,
+    mode: Mode,
+    // last confirmed search query and the rows it matched
+    search: Option<(String, Vec<usize>)>,
+    // names of marked entries, so marks survive sorting and filtering
+    marks: HashMap<char, String>,
+    // selection flags from before each change, newest last
+    undo: VecDeque<Vec<bool>>,
+    redo: Vec<Vec<bool>>,
+    download: Option<Download>,
+    // whether the mock download and connection break down on purpose
+    mock_faults: bool,
+    // set by `:q` to leave the main loop
+    quit: bool,
+    keymap: Keymap,
 }
 
 impl Interface {
-    pub fn new(data: HashMap<String, (u64, String)>) -> Result<Self, Box<dyn Error>> {
-        let widths = widths(&data);
-        let display = display(&data, &widths);
-        let n = display.len();
-        let w = display[0].0.len();
-        let lay = Layout::new(widths, n, w, BORDER);
+    pub fn new(
+        source: Source,
+        server: Option<String>,
+        wrap: bool,
+        bytes: bool,
+        ascii: bool,
+        dest: PathBuf,
+        config: Config,
+    ) -> Result<Self, Box<dyn Error>> {
+        let started = Instant::now();
+        let listing = source.read()?;
+        let took = started.elapsed();
+        let (data, listed) = narrow(listing, &config.only, &config.exclude);
+        if data.is_empty() && listed > 0 {
+            return Err(format!(
+                "none of the {} listed files pass --only and --exclude",
+                listed
+            )
+            .into());
+        }
+        let header = listing_header(server.as_deref(), &source, took, data.len(), listed);
+        let units = (!bytes).then_some(config.units);
+        let unicode = !ascii && unicode_locale();
+        // --ascii wins over glyphs from the config file
+        let checkbox = match config.checkbox {
+            Some(glyphs) if !ascii => glyphs,
+            _ if unicode => ("☑".to_string(), "☐".to_string()),
+            _ => ("[x]".to_string(), "[ ]".to_string()),
+        };
+        let prefix = checkbox_width(&checkbox) + 1;
+        let prefix = prefix + number_width(config.numbers.is_some(), data.len());
+        let parts = Parts {
+            frame: config.frame,
+            title: config.title,
+            pane: config.pane,
+            hints: config.hints,
+        };
+        let size = terminal_size().unwrap_or(FALLBACK_SIZE);
+        let room = row_room(prefix, parts, size.0);
+        let files: Vec<FileEntry> = data
+            .into_iter()
+            .map(|(name, entry)| FileEntry::new(name, entry))
+            .collect();
+        let duplicates = duplicate_groups(&files);
+        let mut cells = Cells {
+            units,
+            hash_chars: FULL_HASH,
+            time_format: &config.time_format,
+            icons: config.icons,
+            unicode,
+            hidden: &[],
+            duplicates: &duplicates,
+        };
+        // --full-hash falls back to short hashes when the hash column would be dropped for them
+        let full_hash = config.full_hash
+            && config.hash_chars > 0
+            && widths(&files, &cells, false, room).hash > 0;
+        if !full_hash {
+            cells.hash_chars = config.hash_chars;
+        }
+        let widths = widths(&files, &cells, false, room);
+        let n = files.len();
+        let order: Vec<usize> = (0..n).collect();
+        let visible = order.clone();
+        let w = files
+            .first()
+            .map_or(EMPTY_WIDTH, |f| row_cells(f, &widths, &cells).width());
+        let lay = Layout::new(widths, n, w, prefix as u16, BORDER, parts, size);
         let pointer = lay.list;
 
-        Ok(Self {
+        let mut interface = Self {
             pointer,
-            data,
-            display,
+            source,
+            only: config.only,
+            exclude: config.exclude,
+            header,
+            server,
+            dest,
+            hide_local: config.hide_local,
+            link: None,
+            connection: None,
+            refresh: None,
+            hashes: None,
+            files,
+            duplicates,
+            order,
+            visible,
+            filter: None,
+            // the listing is ordered by name already
+            sort: Some(SortKey::Name),
+            reverse: false,
             widths,
+            size,
             lay,
             n,
             w,
             index: 0,
-        })
+            offset: 0,
+            units: config.units,
+            bytes,
+            full_hash,
+            hash_chars: config.hash_chars,
+            time_format: config.time_format,
+            icons: config.icons,
+            hidden: Vec::new(),
+            parts,
+            numbers: config.numbers.is_some(),
+            numbering: config.numbers.unwrap_or_default(),
+            unicode,
+            checkbox,
+            count: None,
+            pending: None,
+            wrap,
+            last_click: None,
+            mouse: config.mouse,
+            hover: None,
+            alt_screen: config.alt_screen,
+            status: None,
+            message: None,
+            last_error: None,
+            shown: None,
+            mode: Mode::Normal,
+            search: None,
+            marks: HashMap::new(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            download: None,
+            mock_faults: config.mock_faults,
+            quit: false,
+            scrolloff: config.scrolloff,
+            theme: Theme::new(&config.palette, config.depth),
+            keymap: config.keymap,
+        };
+
+        // what's already downloaded can take the status column and be hidden from the start
+        interface.scan_local();
+        interface.visible = interface.shown();
+        interface.n = interface.visible.len();
+        interface.refresh_layout();
+
+        Ok(interface)
     }
 
     pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        // first, so the threads below can wake the loop from the start
+        let mut input = Input::new();
+
         // use crossbeam-channel for better performance
-        let (winch_tx, winch_rx) = mpsc::channel::<()>();
-        thread::spawn(move || sigwinch_handler(winch_tx).unwrap());
+        let (signal_tx, signal_rx) = mpsc::channel::<Signal>();
+        thread::spawn(move || signal_handler(signal_tx).unwrap());
+
+        if let Some(addr) = self.server.clone() {
+            let (link_tx, link_rx) = mpsc::channel::<Connection>();
+            let faults = self.mock_faults;
+            thread::spawn(move || mock_connection(&addr, faults, link_tx));
+            self.link = Some(link_rx);
+        }
 
-        let mut stdin = async_stdin().bytes();
-        let mut stdout = stdout().lock().into_raw_mode()?.into_alternate_screen()?;
+        // the terminal may have changed size since new, and the columns with --columns
+        self.size = terminal_size()?;
+        self.refresh_layout();
+        self.start_hashing();
 
-        let mut dl_rx: Option<Receiver<()>> = None;
+        // when the last resize or resume came in, until it's been drawn
+        let mut resized: Option<Instant> = None;
+        // undone by terminal::restore once run returns, panics or gets killed
+        terminal::enter(self.mouse, self.alt_screen)?;
+        let mut stdout: RawOut = Screen::new(Box::new(stdout().lock()));
 
         self.clear(&mut stdout)?;
         self.write_layout(&mut stdout)?;
@@ -124,49 +976,406 @@ impl Interface {
 
         // main event loop
         loop {
-            let n = stdin.next();
+            input.wait(self.wait_time(resized))?;
+            let e = input.next()?;
+
+            self.expire_message(&mut stdout)?;
+
+            // a sequence that isn't finished in time is dropped
+            if matches!(self.pending, Some((_, at)) if at.elapsed() > SEQUENCE_TIMEOUT) {
+                self.clear_pending(&mut stdout)?;
+                stdout.flush()?;
+            }
+
+            // coming back from a suspend repaints like a resize
+            while let Ok(signal) = signal_rx.try_recv() {
+                if signal == Signal::Resume {
+                    terminal::enter(self.mouse, self.alt_screen)?;
+                    // whatever ran in the meantime drew over the screen
+                    stdout.invalidate();
+                }
+                resized = Some(Instant::now());
+            }
+
+            // a drag sends a burst of resizes, only the last one is drawn
+            if matches!(resized, Some(at) if at.elapsed() >= RESIZE_DELAY) {
+                resized = None;
+                self.resize(&mut stdout, terminal_size()?)?;
+            } else if self.download.is_some() {
+                self.poll_dl(&mut stdout)?;
+                self.sample_rate();
+                self.tick_spinner(&mut stdout)?;
+            }
+
+            if let Some(state) = self.link.as_ref().and_then(|rx| rx.try_recv().ok()) {
+                self.set_connection(&mut stdout, state)?;
+            }
 
-            if winch_rx.try_recv().is_ok() {
+            if let Some((data, took)) = self.refresh.as_ref().and_then(|rx| rx.try_recv().ok()) {
+                self.refresh = None;
+                match data {
+                    Ok(data) => self.apply_refresh(&mut stdout, data, took)?,
+                    Err(e) => self.report_refresh(&mut stdout, e)?,
+                }
+                stdout.flush()?;
+            }
+
+            // the hash column may get wider, so it's all laid out again
+            if self.apply_hashes() {
+                self.update_widths();
                 self.refresh_layout();
                 self.clear(&mut stdout)?;
                 self.write_layout(&mut stdout)?;
                 stdout.flush()?;
-            } else if let Some(rx) = &dl_rx {
-                if rx.try_recv().is_ok() {
-                    break;
-                }
             }
 
-            if let Some(Ok(k)) = n {
-                let e = parse_event(k, &mut stdin);
+            if let Some(e) = e {
+                // the mouse moving over the list only moves the hover, it doesn't count as input
+                if let Event::Mouse(MouseEvent::Hold(mut x, mut y)) = e {
+                    // a quick move sends a burst of these, only where it ends up is drawn
+                    while let Some(Event::Mouse(MouseEvent::Hold(nx, ny))) =
+                        input.next_if(is_motion)?
+                    {
+                        (x, y) = (nx, ny);
+                    }
+                    self.set_hover(&mut stdout, x, y)?;
+                    continue;
+                }
+
+                // messages only last until the next event
+                if self.message.take().is_some() {
+                    self.write_message(&mut stdout)?;
+                    stdout.flush()?;
+                }
 
-                match e? {
-                    Event::Key(Key::Char('q')) => break,
-                    Event::Key(Key::Char('j')) => {
-                        if self.update_pointer(Direction::Down) {
-                            self.set_pointer(&mut stdout)?;
-                            self.clear_pointer(&mut stdout, Direction::Down)?;
-                        }
+                if let Mode::Command(_) | Mode::Search(..) | Mode::Filter(_) = self.mode {
+                    self.prompt_event(&mut stdout, e)?;
+                    if self.quit {
+                        break;
+                    }
+                    continue;
+                }
+
+                // any key closes the help overlay
+                if self.mode == Mode::Help {
+                    if let Event::Key(_) = e {
+                        self.mode = Mode::Normal;
+                        self.clear(&mut stdout)?;
+                        self.write_layout(&mut stdout)?;
+                        stdout.flush()?;
                     }
-                    Event::Key(Key::Char('k')) => {
-                        if self.update_pointer(Direction::Up) {
-                            self.set_pointer(&mut stdout)?;
-                            self.clear_pointer(&mut stdout, Direction::Up)?;
+                    continue;
+                }
+
+                // only an answer to the quit prompt counts, everything else is ignored
+                if self.mode == Mode::ConfirmQuit {
+                    match e {
+                        Event::Key(Key::Char('y' | 'Y')) => {
+                            if let Some(dl) = self.download.take() {
+                                dl.cancel.store(true, Ordering::Relaxed);
+                            }
+                            break;
                         }
+                        Event::Key(key)
+                            if matches!(key, Key::Char('n' | 'N') | Key::Esc)
+                                || self.keymap.get(key) == Some(Action::Cancel) =>
+                        {
+                            self.mode = Mode::Normal;
+                            self.write_footer(&mut stdout)?;
+                            stdout.flush()?;
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // the detail panel only closes by cancelling or pressing its key again
+                if self.mode == Mode::Detail {
+                    let action = match e {
+                        Event::Key(key) => self.keymap.get(key),
+                        _ => None,
+                    };
+                    if let Some(Action::Cancel | Action::Detail) = action {
+                        self.mode = Mode::Normal;
+                        self.clear(&mut stdout)?;
+                        self.write_layout(&mut stdout)?;
+                        stdout.flush()?;
+                    }
+                    continue;
+                }
+
+                // digits accumulate into a count for the next movement
+                if let Event::Key(Key::Char(c @ '0'..='9')) = e {
+                    if self.pending.is_none() && (c != '0' || self.count.is_some()) {
+                        self.push_count(&mut stdout, c)?;
+                        continue;
+                    }
+                }
+
+                // keys of a sequence wait for the rest of it, keeping the count
+                let (action, arg) = match e {
+                    Event::Key(key) => match self.press(&mut stdout, key)? {
+                        Lookup::Prefix => continue,
+                        Lookup::Action(action) => (Some(action), None),
+                        Lookup::Argument(action, key) => (Some(action), Some(key)),
+                        Lookup::None => (None, None),
+                    },
+                    _ => {
+                        self.clear_pending(&mut stdout)?;
+                        (None, None)
                     }
-                    Event::Key(Key::Char(' ')) => {
-                        self.display[self.index].1 = !self.display[self.index].1;
-                        self.set_pointer(&mut stdout)?;
+                };
+
+                let count = self.take_count(&mut stdout)?;
+
+                // mouse events aren't part of the keymap
+                match e {
+                    Event::Mouse(MouseEvent::Press(MouseButton::Left, x, y)) => {
+                        if let Some(index) = self.row_at(x, y) {
+                            self.click(&mut stdout, index, x)?;
+                        } else if let Some(key) = self.title_at(x, y) {
+                            self.sort_by(&mut stdout, key)?;
+                        }
+                        continue;
                     }
-                    Event::Key(Key::Char('\n')) => {
-                        dl_rx = Some(self.init_dl(&mut stdout)?);
+                    Event::Mouse(MouseEvent::Press(
+                        b @ (MouseButton::WheelUp | MouseButton::WheelDown),
+                        ..,
+                    )) => {
+                        // coalesce a burst of wheel ticks into a single redraw
+                        let mut delta = wheel_delta(b);
+                        while let Some(Event::Mouse(MouseEvent::Press(b, ..))) =
+                            input.next_if(is_wheel)?
+                        {
+                            delta += wheel_delta(b);
+                        }
+
+                        let direction = match delta < 0 {
+                            true => Direction::Up,
+                            false => Direction::Down,
+                        };
+                        self.move_pointer(&mut stdout, direction, delta.unsigned_abs())?;
+                        continue;
                     }
                     _ => {}
                 }
+
+                if let Some(action) = action {
+                    self.act(&mut stdout, action, arg, count)?;
+                }
+                if self.quit {
+                    break;
+                }
             }
         }
 
-        write!(stdout, "{}", cursor::Show).unwrap();
+        // nothing may be left buffered once the main screen is back
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Does what a key bound to `action` does in the list, `count` times where that makes sense.
+    fn act(
+        &mut self,
+        stdout: &mut RawOut,
+        action: Action,
+        arg: Option<Key>,
+        count: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        let rows = count.unwrap_or(1);
+
+        match action {
+            // quitting mid-download abandons the files, so ask first
+            Action::Quit if self.download.is_some() => {
+                self.mode = Mode::ConfirmQuit;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Quit => self.quit = true,
+            Action::MoveDown => {
+                self.move_pointer(stdout, Direction::Down, rows)?;
+            }
+            Action::MoveUp => {
+                self.move_pointer(stdout, Direction::Up, rows)?;
+            }
+            Action::PageDown => {
+                self.move_pointer(stdout, Direction::Down, rows * self.lay.page)?;
+            }
+            Action::PageUp => {
+                self.move_pointer(stdout, Direction::Up, rows * self.lay.page)?;
+            }
+            Action::HalfPageDown => {
+                self.move_pointer(stdout, Direction::Down, rows * self.half_page())?;
+            }
+            Action::HalfPageUp => {
+                self.move_pointer(stdout, Direction::Up, rows * self.half_page())?;
+            }
+            Action::First => {
+                let index = match count {
+                    Some(c) => min(c, self.n).saturating_sub(1),
+                    None => 0,
+                };
+                self.jump_pointer(stdout, index)?;
+            }
+            Action::Last => {
+                // a count jumps to that entry like vim's `5G`
+                let index = match count {
+                    Some(c) => min(c, self.n).saturating_sub(1),
+                    None => self.n.saturating_sub(1),
+                };
+                self.jump_pointer(stdout, index)?;
+            }
+            Action::ScrollCenter => {
+                let above = (self.lay.page - 1) / 2;
+                self.scroll(stdout, self.index.saturating_sub(above))?;
+            }
+            Action::ScrollTop => {
+                self.scroll(stdout, self.index.saturating_sub(self.margin()))?;
+            }
+            Action::ScrollBottom => {
+                let below = self.lay.page - self.margin();
+                self.scroll(stdout, (self.index + 1).saturating_sub(below))?;
+            }
+            Action::Toggle => match self.mode {
+                Mode::Visual(_) => self.end_visual(stdout, true)?,
+                _ => self.toggle(stdout, self.index)?,
+            },
+            Action::ToggleAdvance => {
+                self.toggle_advance(stdout)?;
+            }
+            Action::Visual => match self.mode {
+                Mode::Visual(_) => self.end_visual(stdout, true)?,
+                _ => self.mode = Mode::Visual(self.index),
+            },
+            // one thing per press, a count typed before it was already dropped
+            Action::Cancel => match self.mode {
+                _ if count.is_some() => {}
+                Mode::Visual(_) => self.end_visual(stdout, false)?,
+                _ => self.cancel_dl(stdout)?,
+            },
+            Action::SelectAll => {
+                // a second press clears a full selection again
+                let all = self.visible.iter().all(|e| self.files[*e].selected);
+                self.select_all(stdout, !all)?;
+            }
+            Action::ClearSelection => {
+                self.select_all(stdout, false)?;
+            }
+            Action::Invert => {
+                self.invert_selection(stdout)?;
+            }
+            Action::CopyHash => {
+                self.copy_hash(stdout)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::NextSelected => {
+                self.cycle_selected(stdout, Direction::Down)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::PrevSelected => {
+                self.cycle_selected(stdout, Direction::Up)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::NextMatch => {
+                self.cycle_match(stdout, Direction::Down)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::PrevMatch => {
+                self.cycle_match(stdout, Direction::Up)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Sort => self.next_sort(stdout)?,
+            Action::ReverseSort => self.reverse_sort(stdout)?,
+            Action::Suspend => {
+                // raw mode swallows ^z, so the stop signal is sent by hand
+                stdout.flush()?;
+                terminal::restore();
+                low_level::raise(SIGTSTP)?;
+            }
+            Action::Refresh => {
+                self.init_refresh(stdout)?;
+            }
+            Action::Undo => {
+                self.undo(stdout, rows)?;
+            }
+            Action::Redo => {
+                self.redo(stdout, rows)?;
+            }
+            Action::SetMark => {
+                self.set_mark(arg);
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::JumpMark => {
+                self.jump_mark(stdout, arg)?;
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::LineNumbers => {
+                self.numbers = !self.numbers;
+                self.update_widths();
+                self.update_rows(stdout)?;
+                stdout.flush()?;
+            }
+            Action::HideLocal => {
+                self.hide_local = !self.hide_local;
+                self.update_rows(stdout)?;
+                stdout.flush()?;
+            }
+            Action::ToggleBytes => {
+                self.bytes = !self.bytes;
+                self.update_widths();
+                self.update_rows(stdout)?;
+                stdout.flush()?;
+            }
+            Action::ToggleHash => {
+                self.toggle_hash(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Detail if self.n > 0 => {
+                self.mode = Mode::Detail;
+                self.write_detail(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Help => {
+                self.mode = Mode::Help;
+                self.write_help(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Command => {
+                self.mode = Mode::Command(String::new());
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Search => {
+                self.mode = Mode::Search(String::new(), self.index);
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Filter => {
+                self.mode = Mode::Filter(self.filter.clone().unwrap_or_default());
+                self.write_footer(stdout)?;
+                stdout.flush()?;
+            }
+            Action::Confirm => match count {
+                // a typed number followed by Enter jumps to that entry
+                Some(c) => self.jump_pointer(stdout, min(c, self.n).saturating_sub(1))?,
+                // one transfer at a time
+                None if self.download.is_some() => {
+                    self.message = Some("Already downloading".to_string());
+                    self.write_footer(stdout)?;
+                    stdout.flush()?;
+                }
+                None => self.init_dl(stdout)?,
+            },
+            _ => {}
+        }
 
         Ok(())
     }
@@ -183,237 +1392,4028 @@ impl Interface {
         pos: &(u16, u16),
         text: String,
     ) -> Result<(), Box<dyn Error>> {
-        write!(
+        // a terminal that's too small only gets the notice from write_layout
+        if self.lay.small.is_some() {
+            return Ok(());
+        }
+
+        write!(
             stdout,
             "{}{}{}",
             cursor::Goto(pos.0, pos.1),
             text,
-            style::Reset
+            self.theme.reset
         )?;
 
         Ok(())
     }
 
+    /// Lays everything out again for a terminal of `size` and repaints it all, a download in
+    /// progress included.
+    fn resize(&mut self, stdout: &mut RawOut, size: (u16, u16)) -> Result<(), Box<dyn Error>> {
+        self.size = size;
+        stdout.resize(size);
+        self.update_widths();
+        self.refresh_layout();
+        self.clear(stdout)?;
+        self.write_layout(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
     fn refresh_layout(&mut self) {
-        let new_lay = Layout::new(self.widths, self.n, self.w, BORDER);
-        self.lay = new_lay;
-        self.pointer = self.lay.list;
-        self.index = 0;
+        // an empty list still takes up a row for the "no matches" line
+        self.lay = Layout::new(
+            self.widths,
+            max(self.n, 1),
+            self.w,
+            self.prefix() as u16,
+            BORDER,
+            self.parts,
+            self.size,
+        );
+        // rows moved under the mouse, the next motion finds the new one
+        self.hover = None;
+        // the pointer stays on its entry, scrolled into view at the new height
+        self.place_pointer(min(self.index, self.n.saturating_sub(1)));
     }
 
     fn write_layout(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
-        // header
-        let header = format!(
-            "{}{}Connected to the server at 123.1.2.3:8080",
-            style::Bold,
-            HEADER_COLOR
-        );
-        self.write_line(stdout, &self.lay.header, header)?;
+        // nothing else is drawn until the terminal grows, which repaints like any resize
+        if let Some((w, h)) = self.lay.small {
+            let (tw, _) = self.size;
+            let notice = format!("terminal too small (need at least {}x{})", w, h);
+            write!(
+                stdout,
+                "{}{}{}{}",
+                cursor::Goto(1, 1),
+                self.theme.error,
+                ellipsize(&notice, tw as usize),
+                self.theme.reset
+            )?;
+            return Ok(());
+        }
+
+        if let Some(pos) = self.lay.title {
+            let title = format!("{}{}{}", self.theme.dim, self.theme.title, TITLE);
+            self.write_line(stdout, &pos, title)?;
+        }
+        self.write_header(stdout)?;
 
         // footer
-        let footer = format!("{}{}Press 'q' to quit", style::Bold, FOOTER_COLOR);
-        self.write_line(stdout, &self.lay.footer, footer)?;
+        self.write_footer(stdout)?;
+        self.write_hints(stdout)?;
 
-        // titles
-        let name = format!("{}{}Name", style::Italic, TITLE_COLOR);
-        let size = format!("{}{}Size", style::Italic, TITLE_COLOR);
-        let hash = format!("{}{}SHA-256", style::Italic, TITLE_COLOR);
-        self.write_line(stdout, &self.lay.name, name)?;
-        self.write_line(stdout, &self.lay.size, size)?;
-        self.write_line(stdout, &self.lay.hash, hash)?;
+        self.write_frame(stdout)?;
 
-        // items
-        for (i, d) in self.display.iter().enumerate() {
-            let line = format!(
-                "{}[{}] {}",
-                LIST_COLOR,
-                match d.1 {
-                    true => "x",
-                    false => " ",
-                },
-                d.0
+        // titles, the sort column is underlined
+        for key in self.columns() {
+            let active = match self.sort == Some(key) {
+                true => self.theme.underline.clone(),
+                false => String::new(),
+            };
+            let title = format!(
+                "{}{}{}{}",
+                self.theme.italic,
+                active,
+                self.theme.title,
+                self.title(key)
             );
-            let pos = (self.lay.list.0, self.lay.list.1 + i as u16);
-            self.write_line(stdout, &pos, line)?;
+            self.write_line(stdout, &self.title_pos(key), title)?;
+        }
+
+        if self.widths.status > 0 {
+            let title = format!("{}{}Status", self.theme.italic, self.theme.title);
+            self.write_line(stdout, &self.lay.status, title)?;
         }
 
-        // focus to the first item
+        // items
+        self.write_rows(stdout)?;
+
+        // focus the pointer row
         write!(stdout, "{}", cursor::Goto(self.pointer.0, self.pointer.1))?;
 
+        match self.mode {
+            Mode::Help => self.write_help(stdout)?,
+            Mode::Detail => self.write_detail(stdout)?,
+            _ => {}
+        }
+
         Ok(())
     }
 
-    fn clear_pointer(
-        &self,
-        stdout: &mut RawOut,
-        direction: Direction,
-    ) -> Result<(), Box<dyn Error>> {
-        let (pos, text) = match direction {
-            Direction::Up => (
-                (self.pointer.0, self.pointer.1 + 1),
-                self.display[self.index + 1].clone(),
-            ),
-            Direction::Down => (
-                (self.pointer.0, self.pointer.1 - 1),
-                self.display[self.index - 1].clone(),
-            ),
+    fn write_header(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let indicator = match self.connection {
+            Some(state) => {
+                let color = match state {
+                    Connection::Connected => &self.theme.header,
+                    Connection::Reconnecting => &self.theme.warning,
+                    Connection::Disconnected => &self.theme.error,
+                };
+                let dot = match self.unicode {
+                    true => '●',
+                    false => '*',
+                };
+                (
+                    format!("{}{}{} {}", COL_SEPARATOR, color, dot, state.label()),
+                    COL_SEPARATOR.len() + 2 + state.label().width(),
+                )
+            }
+            None => (String::new(), 0),
         };
 
-        let new = format!(
-            "{}{}[{}] {}",
+        // cut short rather than wrapped onto the titles
+        let (tw, _) = self.size;
+        let room = (tw + 1).saturating_sub(self.lay.header.0) as usize;
+        let header = format!(
+            "{}{}{}{}{}",
             clear::CurrentLine,
-            LIST_COLOR,
-            match text.1 {
-                true => "x",
-                false => " ",
-            },
-            text.0
+            self.theme.bold,
+            self.theme.header,
+            ellipsize(&self.header, room.saturating_sub(indicator.1)),
+            indicator.0
         );
-        self.write_line(stdout, &pos, new)?;
+        self.write_line(stdout, &self.lay.header, header)?;
+
+        Ok(())
+    }
+
+    fn write_frame(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let (left, top, right, bottom) = match self.lay.frame {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        let (h, corners) = match self.unicode {
+            true => ("─", ["┌", "┐", "├", "┤", "└", "┘"]),
+            false => ("-", ["+"; 6]),
+        };
+        let edge = h.repeat((right - left).saturating_sub(1) as usize);
+        let lines = [
+            (top, corners[0], corners[1]),
+            (self.lay.list.1 - 1, corners[2], corners[3]),
+            (bottom, corners[4], corners[5]),
+        ];
+        for (y, start, end) in lines {
+            let text = format!("{}{}{}{}", self.theme.title, start, edge, end);
+            self.write_line(stdout, &(left, y), text)?;
+        }
+
+        // the titles' row, the list's rows get theirs each time they're painted
+        self.write_sides(stdout, top + 1)?;
+        for y in self.lay.list.1..bottom {
+            self.write_sides(stdout, y)?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts back the frame's verticals on line `y`, which clearing the line took with it.
+    fn write_sides(&self, stdout: &mut RawOut, y: u16) -> Result<(), Box<dyn Error>> {
+        let (left, _, right, _) = match self.lay.frame {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        let v = match self.unicode {
+            true => "│",
+            false => "|",
+        };
+        for x in [left, right] {
+            self.write_line(stdout, &(x, y), format!("{}{}", self.theme.title, v))?;
+        }
+
+        Ok(())
+    }
+
+    fn set_connection(
+        &mut self,
+        stdout: &mut RawOut,
+        state: Connection,
+    ) -> Result<(), Box<dyn Error>> {
+        self.connection = Some(state);
+        self.write_header(stdout)?;
+
+        // a transfer can't go on without the server, so it fails instead of hanging
+        if state == Connection::Disconnected && self.download.is_some() {
+            self.fail_dl(stdout, "disconnected from the server")?;
+        }
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn columns(&self) -> Vec<SortKey> {
+        // only what's actually drawn, a narrow terminal drops the hash and then the size
+        SortKey::ALL
+            .into_iter()
+            .filter(|key| self.widths.get(*key) > 0)
+            .collect()
+    }
+
+    fn title(&self, key: SortKey) -> String {
+        let text = key.title();
+
+        // the sort column shows its direction
+        let (down, up) = match self.unicode {
+            true => ("▼", "▲"),
+            false => ("v", "^"),
+        };
+        match (self.sort == Some(key), self.reverse) {
+            (true, true) => format!("{} {}", text, down),
+            (true, false) => format!("{} {}", text, up),
+            (false, _) => text.to_string(),
+        }
+    }
+
+    fn title_pos(&self, key: SortKey) -> (u16, u16) {
+        match key {
+            SortKey::Name => self.lay.name,
+            SortKey::Type => self.lay.kind,
+            // sizes are right-aligned, so their title ends where the column does and the
+            // direction sticks out into the gap instead of shifting it
+            SortKey::Size => {
+                let w = key.title().chars().count();
+                let indent = self.widths.size.saturating_sub(w) as u16;
+                (self.lay.size.0 + indent, self.lay.size.1)
+            }
+            SortKey::Hash => self.lay.hash,
+            SortKey::Modified => self.lay.time,
+        }
+    }
+
+    fn title_at(&self, x: u16, y: u16) -> Option<SortKey> {
+        // only the title text itself is clickable, not the separators
+        self.columns().into_iter().find(|key| {
+            let pos = self.title_pos(*key);
+            let w = self.title(*key).chars().count() as u16;
+            y == pos.1 && (pos.0..pos.0 + w).contains(&x)
+        })
+    }
+
+    fn sort_by(&mut self, stdout: &mut RawOut, key: SortKey) -> Result<(), Box<dyn Error>> {
+        // sorting by the active column again reverses it
+        match self.sort == Some(key) {
+            true => self.reverse = !self.reverse,
+            false => (self.sort, self.reverse) = (Some(key), false),
+        }
+
+        self.sort_entries();
+        self.update_rows(stdout)?;
         stdout.flush()?;
 
         Ok(())
     }
 
+    fn write_help(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let (tw, _) = self.size;
+
+        // generated from the keymap so rebound keys show up here too
+        let mut help = vec![("[count]".to_string(), "repeat a move")];
+        help.extend(
+            Action::ALL
+                .iter()
+                .filter(|a| !self.keymap.keys(**a).is_empty())
+                .map(|a| (self.keymap.describe_keys(*a), a.describe())),
+        );
+        let key_w = help.iter().map(|(k, _)| k.width()).max().unwrap_or(0);
+
+        // lines that don't fit the terminal are cut off, keys may be bound to any character
+        let text_w = (tw as usize).saturating_sub(4);
+        let lines: Vec<String> = help
+            .iter()
+            .map(|(k, d)| format!("{}{}   {}", k, " ".repeat(key_w - k.width()), d))
+            .map(|l| ellipsize(&l, text_w))
+            .collect();
+
+        self.write_box(stdout, "Keybindings", &lines)
+    }
+
+    fn write_detail(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let e = match self.visible.get(self.index) {
+            Some(e) => *e,
+            None => return Ok(()),
+        };
+
+        // long values wrap instead of running past the edge of the terminal
+        let (tw, _) = self.size;
+        let lines = self.detail_lines(e, max((tw as usize).saturating_sub(4), 1));
+
+        self.write_box(stdout, "Details", &lines)
+    }
+
+    /// Everything about entry `e` for the detail popup and the side pane, wrapped at `text_w`.
+    fn detail_lines(&self, e: usize, text_w: usize) -> Vec<String> {
+        let entry = &self.files[e];
+        let name = &entry.name;
+        let modified = entry
+            .mtime
+            .and_then(|t| date::format(t, &self.time_format))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let mut lines = wrap(&format!("Name     {}", name), text_w);
+        let size = match entry.size {
+            Some(size) => format!("{} bytes", size),
+            None => "unknown".to_string(),
+        };
+        lines.extend(wrap(&format!("Size     {}", size), text_w));
+        let hash = match &entry.error {
+            Some(e) => format!("unavailable, {}", e),
+            None if entry.hash.is_empty() => "unknown".to_string(),
+            None => entry.hash.clone(),
+        };
+        lines.extend(wrap(&format!("SHA-256  {}", hash), text_w));
+        if let Some(group) = self.duplicates.get(&entry.hash) {
+            let others: Vec<&str> = group
+                .iter()
+                .filter(|n| *n != name)
+                .map(|n| n.as_str())
+                .collect();
+            lines.extend(wrap(&format!("Copies   {}", others.join(", ")), text_w));
+        }
+        lines.extend(wrap(&format!("Modified {}", modified), text_w));
+        match &self.files[e].status {
+            Status::None => {}
+            Status::Failed(reason) => {
+                lines.extend(wrap(&format!("Status   failed: {}", reason), text_w))
+            }
+            status => lines.extend(wrap(&format!("Status   {}", status.label()), text_w)),
+        }
+        if let Some(error) = &self.last_error {
+            lines.extend(wrap(&format!("Error    {}", error), text_w));
+        }
+
+        lines
+    }
+
+    /// Draws the side pane with the details of the entry under the pointer, if it's open.
+    fn write_pane(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match self.lay.pane {
+            Some((_, top, bottom, _)) => self.write_pane_rows(stdout, top..=bottom),
+            None => Ok(()),
+        }
+    }
+
+    /// Paints the side pane's lines in `rows`, padded to its width so nothing old shows through.
+    fn write_pane_rows(
+        &self,
+        stdout: &mut RawOut,
+        rows: RangeInclusive<u16>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (x, top, bottom, w) = match self.lay.pane {
+            Some(pane) => pane,
+            None => return Ok(()),
+        };
+
+        // a cell for the edge and one either side of the text
+        let text_w = max(w.saturating_sub(3) as usize, 1);
+        let mut lines = vec!["Details".to_string(), String::new()];
+        if let Some(e) = self.visible.get(self.index) {
+            lines.extend(self.detail_lines(*e, text_w));
+        }
+
+        let v = match self.unicode {
+            true => "│",
+            false => "|",
+        };
+        for y in max(*rows.start(), top)..=min(*rows.end(), bottom) {
+            let i = (y - top) as usize;
+            let line = ellipsize(lines.get(i).map_or("", String::as_str), text_w);
+            let style = match i {
+                0 => format!("{}{}", self.theme.italic, self.theme.title),
+                _ => self.theme.list.clone(),
+            };
+            let pad = " ".repeat(text_w.saturating_sub(line.width()));
+            let text = format!("{}{} {}{}{}", self.theme.title, v, style, line, pad);
+            self.write_line(stdout, &(x, y), text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts back what shares line `y` with a row of the list, which clearing the line took with it.
+    fn restore_line(&self, stdout: &mut RawOut, y: u16) -> Result<(), Box<dyn Error>> {
+        self.write_sides(stdout, y)?;
+        self.write_pane_rows(stdout, y..=y)
+    }
+
+    /// The column lines under the table stop before, the side pane's or the terminal's edge.
+    fn line_end(&self) -> u16 {
+        match self.lay.pane {
+            Some((x, ..)) => x - 1,
+            None => self.size.0 + 1,
+        }
+    }
+
+    /// Draws a box with a title in the middle of the terminal, lines must already fit its width.
+    fn write_box(
+        &self,
+        stdout: &mut RawOut,
+        title: &str,
+        lines: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        let (tw, th) = self.size;
+
+        // the box shrinks to the terminal, cutting off lines that don't fit
+        let inner_w = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+        let box_w = min(max(inner_w, title.len()) + 4, tw as usize);
+        let box_h = min(lines.len() + 4, th as usize);
+        let text_w = box_w.saturating_sub(4);
+        let x = (tw - box_w as u16) / 2 + 1;
+        let y = (th - box_h as u16) / 2 + 1;
+
+        let (h, v, corners) = match self.unicode {
+            true => ("─", "│", ["┌", "┐", "└", "┘"]),
+            false => ("-", "|", ["+"; 4]),
+        };
+        let edge = h.repeat(box_w.saturating_sub(2));
+        let title = format!("{:^text_w$}", &title[..min(title.len(), text_w)]);
+        let mut rows = vec![
+            format!("{}{}{}", corners[0], edge, corners[1]),
+            format!("{v} {} {v}", title),
+        ];
+        rows.extend(lines.iter().take(box_h.saturating_sub(4)).map(|l| {
+            let pad = " ".repeat(text_w.saturating_sub(l.width()));
+            format!("{v} {}{} {v}", l, pad)
+        }));
+        rows.push(format!("{v} {:text_w$} {v}", ""));
+        rows.push(format!("{}{}{}", corners[2], edge, corners[3]));
+
+        for (i, row) in rows.iter().enumerate() {
+            let text = format!("{}{}{}", self.theme.pointer_bg, self.theme.title, row);
+            self.write_line(stdout, &(x, y + i as u16), text)?;
+        }
+
+        Ok(())
+    }
+
+    fn clear_pointer(&self, stdout: &mut RawOut, index: usize) -> Result<(), Box<dyn Error>> {
+        let pos = match self.row_pos(index) {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
+        let row = &self.files[self.visible[index]];
+        // stripes follow the entries as they scroll, not the screen rows
+        let bg = match self.visual_range() {
+            Some(range) if range.contains(&index) => self.theme.visual_bg.clone(),
+            _ if index % 2 == 1 => self.theme.stripe.clone(),
+            _ => String::new(),
+        };
+
+        // files that are already downloaded fade into the background
+        let dim = match row.local {
+            true => self.theme.dim.as_str(),
+            false => "",
+        };
+        let hover = match self.hover == Some(pos.1) {
+            true => self.theme.underline.as_str(),
+            false => "",
+        };
+        let base = format!("{}{}{}{}", dim, hover, bg, self.theme.list);
+        self.write_line(stdout, &pos, self.row_line(index, &base))?;
+        self.write_scrollbar(stdout, index)?;
+
+        // the marker sits in the margin, outside of what the row covers
+        if self.theme.marker {
+            let pos = (max(pos.0.saturating_sub(2), 1), pos.1);
+            self.write_line(stdout, &pos, " ".to_string())?;
+        }
+
+        Ok(())
+    }
+
     fn set_pointer(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
-        let new = format!(
-            "{}{}{}{}[{}] {}",
-            clear::CurrentLine,
-            style::Bold,
-            POINTER_BG_COLOR,
-            POINTER_FG_COLOR,
-            match self.display[self.index].1 {
-                true => "x",
-                false => " ",
-            },
-            self.display[self.index].0
+        if self.visible.get(self.index).is_none() {
+            return Ok(());
+        }
+
+        let base = format!(
+            "{}{}{}",
+            self.theme.bold, self.theme.pointer_bg, self.theme.pointer_fg
         );
-        self.write_line(stdout, &self.pointer, new)?;
-        stdout.flush()?;
+        self.write_line(stdout, &self.pointer, self.row_line(self.index, &base))?;
+        // the pane follows the pointer, so all of it changes
+        self.write_pane(stdout)?;
+        self.write_scrollbar(stdout, self.index)?;
+
+        if self.theme.marker {
+            let pos = (max(self.pointer.0.saturating_sub(2), 1), self.pointer.1);
+            self.write_line(stdout, &pos, ">".to_string())?;
+        }
 
         Ok(())
     }
 
-    fn update_pointer(&mut self, direction: Direction) -> bool {
-        match direction {
-            Direction::Up => {
-                if self.index > 0 && self.index <= self.n {
-                    self.pointer.1 -= 1;
-                    self.index -= 1;
+    /// Row `index` in `base`, padded out to the table's width so its background is one block.
+    fn row_line(&self, index: usize, base: &str) -> String {
+        let e = self.visible[index];
+        let file = &self.files[e];
+        let text = row_cells(file, &self.widths, &self.cells());
+        let status = self.status_cell(file);
 
-                    return true;
-                }
+        let status_w = match self.widths.status {
+            0 => 0,
+            w => COL_SPACING as usize + w,
+        };
+        let used = self.prefix() + text.width() + status_w;
+        let pad = " ".repeat((self.prefix() + self.w).saturating_sub(used));
+
+        format!(
+            "{}{}{} {}{}{}",
+            base,
+            self.number(index),
+            self.checkbox(file.selected),
+            self.highlight(e, &text, base),
+            status,
+            pad
+        )
+    }
+
+    /// `text` of entry `e` with what the search or filter matched highlighted, back in `base` after.
+    fn highlight(&self, e: usize, text: &str, base: &str) -> String {
+        let name = &self.files[e].name;
+        let positions = match (&self.mode, &self.search, &self.filter) {
+            (Mode::Search(query, _), _, _) | (_, Some((query, _)), _) => {
+                pattern::search_positions(query, name)
             }
-            Direction::Down => {
-                if self.index < self.n - 1 {
-                    self.pointer.1 += 1;
-                    self.index += 1;
+            (_, _, Some(query)) => pattern::filter_positions(query, name),
+            _ => Vec::new(),
+        };
+        if positions.is_empty() || self.theme.highlight.is_empty() || self.widths.name == 0 {
+            return text.to_string();
+        }
+
+        // the text starts with the name, but a cut one only has its first characters
+        let shown = ellipsize(name, self.widths.name);
+        let kept = match shown == *name {
+            true => name.chars().count(),
+            false => shown.chars().count() - 1,
+        };
 
-                    return true;
+        // escapes take no room, so the padding after the name still lines up
+        let mut out = String::new();
+        for (i, c) in text.chars().enumerate() {
+            let matched = i < kept && positions.contains(&i);
+            let prev = i > 0 && i - 1 < kept && positions.contains(&(i - 1));
+            match (matched, prev) {
+                (true, false) => out.push_str(&self.theme.highlight),
+                (false, true) => {
+                    out.push_str(&self.theme.reset);
+                    out.push_str(base);
                 }
+                _ => {}
             }
+            out.push(c);
         }
 
-        false
+        out
     }
 
-    fn init_dl(&self, stdout: &mut RawOut) -> Result<Receiver<()>, Box<dyn Error>> {
-        let footer = format!(
-            "{}{}Downloading the selected files...",
-            style::Bold,
-            FOOTER_COLOR
+    fn status_cell(&self, file: &FileEntry) -> String {
+        if self.widths.status == 0 {
+            return String::new();
+        }
+
+        let color = match file.status {
+            Status::Failed(_) => self.theme.error.as_str(),
+            _ => "",
+        };
+        let cell = match (&file.status, file.local, self.unicode) {
+            (Status::None, true, true) => "✓ local".to_string(),
+            (Status::None, true, false) => "local".to_string(),
+            // without a size there's nothing to show a share of, just what has arrived
+            (Status::Transferring(done, None), _, _) => self.size_text(*done),
+            (status, _, _) => status.cell(self.widths.status, self.unicode),
+        };
+        // padded by terminal cells like the other columns, so the row ends where the table does
+        let cell = ellipsize(&cell, self.widths.status);
+        let pad = " ".repeat(self.widths.status.saturating_sub(cell.width()));
+        format!("{}{}{}{}", COL_SEPARATOR, color, cell, pad)
+    }
+
+    fn prefix(&self) -> usize {
+        let count = match self.numbering {
+            Numbering::Display => self.n,
+            Numbering::Absolute => self.files.len(),
+        };
+        checkbox_width(&self.checkbox) + 1 + number_width(self.numbers, count)
+    }
+
+    /// The glyph for a row's checkbox, padded so both glyphs take the same cells.
+    fn checkbox(&self, selected: bool) -> String {
+        let glyph = match selected {
+            true => &self.checkbox.0,
+            false => &self.checkbox.1,
+        };
+        let pad = checkbox_width(&self.checkbox) - glyph.width();
+
+        format!("{}{}", glyph, " ".repeat(pad))
+    }
+
+    fn number(&self, index: usize) -> String {
+        if !self.numbers {
+            return String::new();
+        }
+
+        let number = match self.numbering {
+            Numbering::Display => index + 1,
+            Numbering::Absolute => self.visible[index] + 1,
+        };
+        let width = self.prefix() - checkbox_width(&self.checkbox) - 2;
+        format!("{:>width$} ", number, width = width)
+    }
+
+    fn write_scrollbar(&self, stdout: &mut RawOut, index: usize) -> Result<(), Box<dyn Error>> {
+        // short lists that fit on screen don't get one
+        let (n, page) = (self.n, self.lay.page);
+        if n <= page {
+            return Ok(());
+        }
+
+        // the thumb covers the share of the list in view and reaches the ends with it
+        let size = max(page * page / n, 1);
+        let start = (self.offset * (page - size) + (n - page) / 2) / (n - page);
+        let row = index - self.offset;
+        let (thumb, track) = match self.unicode {
+            true => ("█", "│"),
+            false => ("#", "|"),
+        };
+        let cell = match (start..start + size).contains(&row) {
+            true => thumb,
+            false => track,
+        };
+
+        let pos = (
+            self.lay.list.0 + (self.prefix() + self.w) as u16 + 2,
+            self.lay.list.1 + row as u16,
         );
-        self.write_line(stdout, &self.lay.footer, footer)?;
-        stdout.flush()?;
+        self.write_line(stdout, &pos, format!("{}{}", self.theme.title, cell))?;
 
-        let filenames: Vec<String> = self
-            .display
-            .iter()
-            .enumerate()
-            .filter(|(_, (_, b))| *b)
-            .map(|(i, _)| self.data.keys().nth(i).unwrap().clone())
-            .collect();
+        Ok(())
+    }
+
+    fn move_pointer(
+        &mut self,
+        stdout: &mut RawOut,
+        direction: Direction,
+        rows: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.n == 0 {
+            return Ok(());
+        }
 
-        let (dl_tx, dl_rx) = mpsc::channel::<()>();
-        thread::spawn(move || mock(&filenames, dl_tx).unwrap());
+        let index = match direction {
+            Direction::Up if self.wrap && self.index == 0 => self.n - 1,
+            Direction::Down if self.wrap && self.index == self.n - 1 => 0,
+            Direction::Up => self.index.saturating_sub(rows),
+            Direction::Down => min(self.index + rows, self.n - 1),
+        };
 
-        Ok(dl_rx)
+        self.jump_pointer(stdout, index)
     }
-}
 
-fn rand_string(limit: Option<usize>) -> String {
-    let len = match limit {
-        Some(limit) => limit,
-        None => rand::thread_rng().gen_range(5..30),
-    };
-    Alphanumeric.sample_string(&mut rand::thread_rng(), len)
-}
+    fn push_count(&mut self, stdout: &mut RawOut, digit: char) -> Result<(), Box<dyn Error>> {
+        let digit = digit.to_digit(10).unwrap() as usize;
+        self.count = Some(min(self.count.unwrap_or(0) * 10 + digit, MAX_COUNT));
+        self.write_count(stdout)?;
+        stdout.flush()?;
 
-fn widths(data: &HashMap<String, (u64, String)>) -> (usize, usize, usize) {
-    let mut max_name = 0;
-    let mut max_size = 0;
-    let mut max_hash = 0;
+        Ok(())
+    }
 
-    data.iter().for_each(|(name, (size, hash))| {
-        max_name = max(max_name, name.len());
-        max_size = max(max_size, size.to_string().len());
-        max_hash = max(max_hash, hash.len());
-    });
+    fn press(&mut self, stdout: &mut RawOut, key: Key) -> Result<Lookup, Box<dyn Error>> {
+        let mut keys = match self.pending.take() {
+            Some((keys, _)) => keys,
+            None => Vec::new(),
+        };
+        let pending = !keys.is_empty();
 
-    (max_name, max_size, max_hash)
-}
+        // Esc only drops a pending sequence
+        if key == Key::Esc && pending {
+            self.write_footer(stdout)?;
+            stdout.flush()?;
+            return Ok(Lookup::None);
+        }
 
-fn display(
-    data: &HashMap<String, (u64, String)>,
-    widths: &(usize, usize, usize),
-) -> Vec<(String, bool)> {
-    let mut display = Vec::new();
+        keys.push(key);
+        let mut lookup = self.keymap.lookup(&keys);
 
-    data.iter().for_each(|(name, (size, hash))| {
-        let mut d = String::new();
+        // a key that doesn't continue the sequence counts on its own
+        if lookup == Lookup::None && pending {
+            keys = vec![key];
+            lookup = self.keymap.lookup(&keys);
+        }
 
-        // correct alignment in the table
-        d.push_str(format!("{:width$}", name, width = widths.0).as_str());
-        d.push_str(COL_SEPARATOR);
-        d.push_str(format!("{:width$}", size, width = widths.1).as_str());
-        d.push_str(COL_SEPARATOR);
-        d.push_str(&format!("{}...", &hash[..20]));
+        if lookup == Lookup::Prefix {
+            self.pending = Some((keys, Instant::now()));
+        }
+        if pending || lookup == Lookup::Prefix {
+            self.write_footer(stdout)?;
+            stdout.flush()?;
+        }
 
-        display.push((d, false));
-    });
+        Ok(lookup)
+    }
 
-    display
-}
+    fn clear_pending(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        if self.pending.take().is_some() {
+            self.write_footer(stdout)?;
+        }
 
-fn sigwinch_handler(tx: Sender<()>) -> Result<(), Box<dyn Error>> {
-    // for contego's async context: tokio::signal::unix::{signal, SignalKind}
-    let mut signals = Signals::new([SIGWINCH])?;
+        Ok(())
+    }
+
+    fn take_count(&mut self, stdout: &mut RawOut) -> Result<Option<usize>, Box<dyn Error>> {
+        let count = self.count.take();
+        if count.is_some() {
+            self.write_footer(stdout)?;
+        }
 
-    for _ in &mut signals {
-        tx.send(())?;
+        Ok(count)
     }
 
-    Ok(())
-}
+    fn write_footer(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let prompt = match &self.mode {
+            Mode::Command(cmd) => Some(format!(":{}", cmd)),
+            Mode::Search(query, _) => Some(match self.find_match(query) {
+                None if !query.is_empty() => {
+                    format!("/{}{}{}no match", query, COL_SEPARATOR, self.theme.error)
+                }
+                _ => format!("/{}", query),
+            }),
+            Mode::Filter(query) => Some(format!("filter: {}", query)),
+            Mode::ConfirmQuit => Some("Download in progress — quit anyway? (y/n)".to_string()),
+            _ => None,
+        };
 
-fn mock(_filenames: &[String], tx: Sender<()>) -> Result<(), Box<dyn Error>> {
-    // mock function for sending client requests
-    thread::sleep(Duration::from_secs(5));
-    tx.send(())?;
+        if let Some(prompt) = prompt {
+            let prompt = format!(
+                "{}{}{}{}",
+                clear::CurrentLine,
+                self.theme.bold,
+                self.theme.footer,
+                prompt
+            );
+            self.write_line(stdout, &self.lay.footer, prompt)?;
+            self.write_pane_rows(stdout, self.lay.footer.1..=self.lay.footer.1)?;
+            self.write_message(stdout)?;
 
-    Ok(())
-}
+            return Ok(());
+        }
 
-fn main() {
-    let mut data = HashMap::new();
-    (0..20).into_iter().for_each(|_| {
-        let filename = rand_string(None);
-        let filesize = rand::thread_rng().gen_range(100..1000000);
-        let hash = rand_string(Some(64));
+        let status = match (&self.download, &self.status) {
+            (Some(dl), _) => self.download_progress(dl),
+            (None, Some(status)) => status.clone(),
+            (None, None) => match self.keymap.keys(Action::Quit).first() {
+                Some(key) => format!("Press '{}' to quit", keymap::sequence_name(key)),
+                None => String::new(),
+            },
+        };
+        // the download progress already has the selected total in it
+        let selected = match &self.download {
+            Some(_) => String::new(),
+            None => format!("{}{}", COL_SEPARATOR, self.selected_summary()),
+        };
+        let (filter, filtered) = match &self.filter {
+            Some(query) => (
+                format!("{}filter: {}", COL_SEPARATOR, query),
+                format!(" (filtered from {})", self.files.len()),
+            ),
+            None => (String::new(), String::new()),
+        };
+        let position = format!("{}/{}{}", min(self.index + 1, self.n), self.n, filtered);
 
-        data.insert(filename, (filesize, hash));
-    });
+        let footer = format!(
+            "{}{}{}{}{}{}{}{}",
+            clear::CurrentLine,
+            self.theme.bold,
+            self.theme.footer,
+            status,
+            COL_SEPARATOR,
+            position,
+            selected,
+            filter
+        );
+        self.write_line(stdout, &self.lay.footer, footer)?;
+        self.write_pane_rows(stdout, self.lay.footer.1..=self.lay.footer.1)?;
+        self.write_count(stdout)?;
+        self.write_message(stdout)?;
+
+        Ok(())
+    }
+
+    /// Writes a line for each group of hints, leaving out the hints of unbound actions and those
+    /// that don't fit.
+    fn write_hints(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let pos = match self.lay.hints {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
 
-    let mut interface = Interface::new(data).unwrap();
-    interface.run().unwrap();
+        let room = self.line_end().saturating_sub(pos.0) as usize;
+        let label_w = keymap::HINTS.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+        for (i, (label, hints)) in keymap::HINTS.iter().enumerate() {
+            let mut line = format!(
+                "{}{}{}{:label_w$}{}",
+                clear::CurrentLine,
+                self.theme.italic,
+                self.theme.title,
+                label,
+                self.theme.reset
+            );
+            let mut used = label_w;
+            for (action, text) in hints.iter() {
+                let key = match self.keymap.keys(*action).first() {
+                    Some(key) => keymap::sequence_name(key),
+                    None => continue,
+                };
+                used += 2 + key.width() + 1 + text.width();
+                if used > room {
+                    break;
+                }
+                line.push_str(&format!(
+                    "  {}{}{}{}{} {}",
+                    self.theme.bold,
+                    self.theme.footer,
+                    key,
+                    self.theme.reset,
+                    self.theme.footer,
+                    text
+                ));
+            }
+
+            let y = pos.1 + i as u16;
+            self.write_line(stdout, &(pos.0, y), line)?;
+            self.write_pane_rows(stdout, y..=y)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_message(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let text = self.message.as_deref().unwrap_or_default();
+        let color = match text.starts_with(ERROR_PREFIX) {
+            true => &self.theme.error,
+            false => &self.theme.footer,
+        };
+
+        // one line only, long errors are cut at the edge of the terminal or the side pane
+        let room = self.line_end().saturating_sub(self.lay.message.0) as usize;
+        let message = format!("{}{}{}", clear::CurrentLine, color, ellipsize(text, room));
+        self.write_line(stdout, &self.lay.message, message)?;
+        self.write_pane_rows(stdout, self.lay.message.1..=self.lay.message.1)?;
+
+        Ok(())
+    }
+
+    /// How long the main loop may wait for input before something on screen is due to change.
+    fn wait_time(&self, resized: Option<Instant>) -> Duration {
+        let message = match (&self.message, &self.shown) {
+            (Some(message), Some((shown, at))) if message == shown => Some(*at + MESSAGE_TIMEOUT),
+            // a new message starts its timeout on the next round
+            (Some(_), _) => Some(Instant::now()),
+            (None, _) => None,
+        };
+
+        [
+            message,
+            self.pending.as_ref().map(|(_, at)| *at + SEQUENCE_TIMEOUT),
+            self.download.as_ref().map(|dl| dl.ticked + SPINNER_TICK),
+            resized.map(|at| at + RESIZE_DELAY),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map_or(IDLE_WAIT, |due| due.saturating_duration_since(Instant::now()))
+    }
+
+    fn expire_message(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match (&self.message, &self.shown) {
+            (Some(message), Some((shown, at))) if message == shown => {
+                if at.elapsed() < MESSAGE_TIMEOUT {
+                    return Ok(());
+                }
+                self.message = None;
+                self.shown = None;
+                self.write_message(stdout)?;
+                stdout.flush()?;
+            }
+            // a message that replaced another one gets the full time too
+            (Some(message), _) => self.shown = Some((message.clone(), Instant::now())),
+            (None, _) => self.shown = None,
+        }
+
+        Ok(())
+    }
+
+    /// The selected files and how their download went, for the main screen once the interface
+    /// is gone. Empty on the alternate screen, whose contents disappear on exit anyway.
+    pub fn summary(&self) -> Vec<String> {
+        if self.alt_screen {
+            return Vec::new();
+        }
+
+        let mut lines = vec![self.selected_summary()];
+        for file in self.files.iter().filter(|f| f.selected) {
+            let name = &file.name;
+            let line = match &file.status {
+                Status::None => format!("  {}", name),
+                Status::Failed(e) => format!("  {:6} {} ({})", file.status.label(), name, e),
+                status => format!("  {:6} {}", status.label(), name),
+            };
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    fn selected_summary(&self) -> String {
+        let (count, total, unknown) = self.files.iter().filter(|f| f.selected).fold(
+            (0, 0, false),
+            |(count, total, unknown), file| match file.size {
+                Some(size) => (count + 1, total + size, unknown),
+                None => (count + 1, total, true),
+            },
+        );
+
+        let files = match count {
+            1 => "file",
+            _ => "files",
+        };
+
+        match count {
+            0 => "0 files selected".to_string(),
+            _ => format!(
+                "{} {} selected · {}",
+                count,
+                files,
+                self.total_text(total, unknown)
+            ),
+        }
+    }
+
+    /// `total` as a size, noting that files without one aren't in it.
+    fn total_text(&self, total: u64, unknown: bool) -> String {
+        match unknown {
+            true => format!("{} + unknown", self.size_text(total)),
+            false => self.size_text(total),
+        }
+    }
+
+    fn size_text(&self, size: u64) -> String {
+        match self.size_units() {
+            Some(units) => size::format(size, Some(units)),
+            None => format!("{} bytes", size),
+        }
+    }
+
+    /// Files started so far and the bytes of them that have arrived.
+    fn download_done(&self) -> (usize, u64) {
+        let arrived = self.download.as_ref().map_or(0, |dl| dl.arrived);
+        self.files
+            .iter()
+            .fold((0, arrived), |(files, bytes), file| match file.status {
+                Status::Transferring(done, _) => (files + 1, bytes + done),
+                // files without a size are in `arrived` instead
+                Status::Done => (files + 1, bytes + file.size.unwrap_or(0)),
+                Status::Failed(_) => (files + 1, bytes),
+                _ => (files, bytes),
+            })
+    }
+
+    fn download_progress(&self, dl: &Download) -> String {
+        let (files, done) = self.download_done();
+        let (tw, _) = self.size;
+        let bar = (tw as usize / 6).clamp(MIN_BAR_WIDTH, MAX_BAR_WIDTH);
+
+        let spinner = match self.unicode {
+            true => SPINNER[dl.frame % SPINNER.len()],
+            false => ASCII_SPINNER[dl.frame % ASCII_SPINNER.len()],
+        };
+
+        // the estimate only changes with the samples, so it doesn't flicker on every repaint
+        let (speed, eta) = match (dl.rate, dl.samples.back()) {
+            (Some(rate), Some((_, sampled))) => (
+                format!("{}/s", self.size_text(rate as u64)),
+                eta_text(dl.total.saturating_sub(*sampled) as f64 / rate),
+            ),
+            _ => ("--/s".to_string(), "--:--".to_string()),
+        };
+
+        let sizes = format!(
+            "{} / {}",
+            self.size_text(done),
+            self.total_text(dl.total, dl.unknown)
+        );
+        // with files of unknown size there's no share of the whole or time left to show
+        match dl.unknown {
+            true => format!(
+                "{} Downloading {}/{} · {} · {}",
+                spinner,
+                max(files, 1),
+                dl.files,
+                sizes,
+                speed
+            ),
+            false => format!(
+                "{} Downloading {}/{} · {} · {} {}% · {} · ETA {}",
+                spinner,
+                max(files, 1),
+                dl.files,
+                sizes,
+                progress_bar(done, dl.total, bar, '-'),
+                done * 100 / max(dl.total, 1),
+                speed,
+                eta
+            ),
+        }
+    }
+
+    fn sample_rate(&mut self) {
+        let due = self.download.as_ref().is_some_and(|dl| {
+            dl.samples
+                .back()
+                .is_none_or(|(at, _)| at.elapsed() >= SAMPLE_INTERVAL)
+        });
+        if !due {
+            return;
+        }
+
+        let (_, done) = self.download_done();
+        let dl = match &mut self.download {
+            Some(dl) => dl,
+            None => return,
+        };
+        dl.samples.push_back((Instant::now(), done));
+        if dl.samples.len() > RATE_SAMPLES {
+            dl.samples.pop_front();
+        }
+
+        // the average over the whole window, since single deltas jump around with each chunk
+        dl.rate = match (dl.samples.front(), dl.samples.back()) {
+            (Some((first, from)), Some((last, to))) if dl.samples.len() >= MIN_RATE_SAMPLES => {
+                let secs = last.duration_since(*first).as_secs_f64();
+                // a failed file takes its bytes with it, so the count can go down
+                let rate = to.saturating_sub(*from) as f64 / secs;
+                (rate > 0.0).then_some(rate)
+            }
+            _ => None,
+        };
+    }
+
+    fn prompt_event(&mut self, stdout: &mut RawOut, e: Event) -> Result<(), Box<dyn Error>> {
+        let buf = match &mut self.mode {
+            Mode::Command(buf) | Mode::Search(buf, _) | Mode::Filter(buf) => buf,
+            _ => return Ok(()),
+        };
+
+        match e {
+            Event::Key(Key::Char('\n')) => self.accept_prompt(stdout)?,
+            Event::Key(Key::Esc) => self.cancel_prompt(stdout)?,
+            // cancel keys like emacs' ^g work too, unless they are something to type
+            Event::Key(key @ (Key::Ctrl(_) | Key::Alt(_)))
+                if self.keymap.get(key) == Some(Action::Cancel) =>
+            {
+                self.cancel_prompt(stdout)?
+            }
+            // backspacing over the prompt character leaves it like vim does
+            Event::Key(Key::Backspace) if buf.is_empty() => self.cancel_prompt(stdout)?,
+            Event::Key(Key::Backspace) => {
+                buf.pop();
+                self.prompt_changed(stdout)?;
+            }
+            Event::Key(Key::Char(c)) => {
+                buf.push(c);
+                self.prompt_changed(stdout)?;
+            }
+            _ => {}
+        }
+
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn prompt_changed(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match &self.mode {
+            // searching is incremental, the pointer follows the first match
+            Mode::Search(query, origin) => {
+                let index = match self.find_match(query) {
+                    Some(i) => Some(i),
+                    None if query.is_empty() => Some(*origin),
+                    None => None,
+                };
+                if let Some(index) = index {
+                    self.jump_pointer(stdout, index)?;
+                }
+                // the highlights follow the query as it's typed
+                self.write_rows(stdout)?;
+            }
+            // and so is filtering, the list narrows while typing
+            Mode::Filter(query) => {
+                self.filter = (!query.is_empty()).then(|| query.clone());
+                self.update_rows(stdout)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn accept_prompt(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Command(cmd) => self.run_command(stdout, cmd.trim())?,
+            Mode::Search(query, _) if !query.is_empty() => {
+                let matches = self.find_matches(&query);
+                self.search = Some((query, matches));
+                self.write_match_position();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn cancel_prompt(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Search(_, origin) => {
+                self.jump_pointer(stdout, origin)?;
+                self.write_rows(stdout)?;
+            }
+            // cancelling the filter prompt restores the full list
+            Mode::Filter(_) => {
+                self.filter = None;
+                self.update_rows(stdout)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn sort_entries(&mut self) {
+        let key = match self.sort {
+            Some(key) => key,
+            None => return,
+        };
+
+        let files = &self.files;
+        self.order.sort_by(|a, b| {
+            let (a, b) = (&files[*a], &files[*b]);
+            let ord = match key {
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Type => filetype::extension(&a.name).cmp(&filetype::extension(&b.name)),
+                SortKey::Size => a.size.cmp(&b.size),
+                SortKey::Hash => a.hash.cmp(&b.hash),
+                SortKey::Modified => a.mtime.cmp(&b.mtime),
+            };
+            let ord = ord.then_with(|| a.name.cmp(&b.name));
+            let ord = match self.reverse {
+                true => ord.reverse(),
+                false => ord,
+            };
+
+            // entries without a time or a size go last whichever way the rest is sorted
+            match key {
+                SortKey::Modified => a.mtime.is_none().cmp(&b.mtime.is_none()).then(ord),
+                SortKey::Size => a.size.is_none().cmp(&b.size.is_none()).then(ord),
+                _ => ord,
+            }
+        });
+    }
+
+    /// Rebuilds the shown rows from the sort order and filter, then repaints everything.
+    fn update_rows(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        // keep the pointer on the same entry while it's still shown
+        let entry = self.visible.get(self.index).copied();
+        self.show_rows(stdout, entry, 0)
+    }
+
+    /// Like `update_rows`, with the pointer on `entry` if it's shown and on `row` otherwise.
+    fn show_rows(
+        &mut self,
+        stdout: &mut RawOut,
+        entry: Option<usize>,
+        row: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.visible = self.shown();
+        self.n = self.visible.len();
+        // rows of a previous search or visual range no longer line up
+        self.search = None;
+        if let Mode::Visual(_) = self.mode {
+            self.mode = Mode::Normal;
+        }
+
+        self.refresh_layout();
+        let index = entry
+            .and_then(|e| self.visible.iter().position(|v| *v == e))
+            .unwrap_or(min(row, self.n.saturating_sub(1)));
+        self.place_pointer(index);
+
+        self.clear(stdout)?;
+        self.write_layout(stdout)?;
+
+        Ok(())
+    }
+
+    /// Entries in sorted order that the filter and `hide_local` leave in the list.
+    fn shown(&self) -> Vec<usize> {
+        self.order
+            .iter()
+            .copied()
+            .filter(|e| match &self.filter {
+                Some(query) => pattern::filter_match(query, &self.files[*e].name),
+                None => true,
+            })
+            .filter(|e| !(self.hide_local && self.files[*e].local))
+            .collect()
+    }
+
+    /// Marks the entries that are already in the download directory, returning whether any changed.
+    fn scan_local(&mut self) -> bool {
+        let files = local_files(&self.dest);
+        let mut changed = false;
+        for file in &mut self.files {
+            let local = file
+                .size
+                .is_some_and(|size| files.get(&file.name) == Some(&size));
+            changed |= file.local != local;
+            file.local = local;
+        }
+
+        // the markers may need the status column
+        self.update_widths();
+
+        changed
+    }
+
+    fn init_refresh(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        if self.refresh.is_some() {
+            return Ok(());
+        }
+
+        self.status = Some("refreshing…".to_string());
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        // the source may be slow, so it runs off the ui thread
+        let (tx, rx) = mpsc::channel();
+        let source = self.source.clone();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let listing = source.read();
+            tx.send((listing, started.elapsed())).ok();
+            input::wake();
+        });
+        self.refresh = Some(rx);
+
+        Ok(())
+    }
+
+    fn apply_refresh(
+        &mut self,
+        stdout: &mut RawOut,
+        data: BTreeMap<String, Entry>,
+        took: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let (data, listed) = narrow(data, &self.only, &self.exclude);
+        let pointed = self
+            .visible
+            .get(self.index)
+            .map(|e| self.files[*e].name.clone());
+        let mut kept: HashMap<String, (bool, Status)> = self
+            .files
+            .drain(..)
+            .map(|f| (f.name, (f.selected, f.status)))
+            .collect();
+
+        // selections and statuses only carry over for entries that are still listed
+        self.files = data
+            .into_iter()
+            .map(|(name, entry)| {
+                let kept = kept.remove(&name);
+                let mut file = FileEntry::new(name, entry);
+                (file.selected, file.status) = kept.unwrap_or((false, Status::None));
+                file
+            })
+            .collect();
+        self.duplicates = duplicate_groups(&self.files);
+        self.start_hashing();
+        self.scan_local();
+
+        // old entry indices mean nothing for the new listing
+        self.undo.clear();
+        self.redo.clear();
+        self.order = (0..self.files.len()).collect();
+        self.sort_entries();
+
+        if self.status.as_deref() == Some("refreshing…") {
+            self.status = None;
+        }
+        // the fetch took its own time and the globs may leave a different share
+        self.header = listing_header(
+            self.server.as_deref(),
+            &self.source,
+            took,
+            self.files.len(),
+            listed,
+        );
+        self.write_header(stdout)?;
+        let entry = pointed.and_then(|name| self.position(&name));
+        let row = self.index;
+        self.show_rows(stdout, entry, row)
+    }
+
+    /// Hashes the files of the listed directory that don't have a digest yet on a few threads,
+    /// dropping the results of any earlier run.
+    fn start_hashing(&mut self) {
+        let dir = match &self.source {
+            Source::Dir(dir) => dir,
+            _ => return,
+        };
+
+        // small files first, so most of the column fills in quickly
+        let mut pending: Vec<(u64, &String)> = self
+            .files
+            .iter()
+            .filter(|file| file.hash == PENDING_HASH)
+            .map(|file| (file.size.unwrap_or(0), &file.name))
+            .collect();
+        pending.sort();
+        let queue: VecDeque<String> = pending.into_iter().map(|(_, name)| name.clone()).collect();
+        let queue = Arc::new(Mutex::new(queue));
+
+        let (tx, rx) = mpsc::channel();
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        for _ in 0..min(workers, MAX_HASHERS) {
+            let (queue, tx, dir) = (queue.clone(), tx.clone(), dir.clone());
+            thread::spawn(move || loop {
+                let name = match queue.lock().unwrap().pop_front() {
+                    Some(name) => name,
+                    None => return,
+                };
+                let digest = File::open(dir.join(&name))
+                    .and_then(sha256::hex_digest)
+                    .map_err(|e| e.kind().to_string());
+                // a newer listing took over, its own threads hash it
+                if tx.send((name, digest)).is_err() {
+                    return;
+                }
+                input::wake();
+            });
+        }
+        self.hashes = Some(rx);
+    }
+
+    /// Puts the digests finished since the last call into the listing, returning whether
+    /// there were any.
+    fn apply_hashes(&mut self) -> bool {
+        let rx = match &self.hashes {
+            Some(rx) => rx,
+            None => return false,
+        };
+
+        let mut changed = false;
+        for (name, digest) in rx.try_iter() {
+            let file = match self.position(&name) {
+                Some(e) => &mut self.files[e],
+                None => continue,
+            };
+            match digest {
+                Ok(hash) => file.hash = hash,
+                Err(e) => {
+                    file.hash = String::new();
+                    file.error = Some(e);
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            self.duplicates = duplicate_groups(&self.files);
+        }
+
+        changed
+    }
+
+    fn report_refresh(&mut self, stdout: &mut RawOut, e: String) -> Result<(), Box<dyn Error>> {
+        // the old listing stays up, so nothing is lost and the refresh can be tried again
+        if self.status.as_deref() == Some("refreshing…") {
+            self.status = None;
+        }
+        self.report(format!("couldn't refresh the listing: {}", e));
+        self.write_footer(stdout)?;
+
+        Ok(())
+    }
+
+    /// Shows `error` on the message line, keeping all of it for the detail view.
+    fn report(&mut self, error: String) {
+        self.message = Some(format!("{}{}", ERROR_PREFIX, error));
+        self.last_error = Some(error);
+    }
+
+    /// Switches between short and full hashes, refusing full ones that would wrap the rows.
+    fn toggle_hash(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        if self.hash_chars == 0 || self.hidden.contains(&SortKey::Hash) {
+            self.message = Some("The hash column is hidden".to_string());
+            return self.write_footer(stdout);
+        }
+
+        self.full_hash = !self.full_hash;
+        self.update_widths();
+        if self.full_hash && self.widths.hash == 0 {
+            self.full_hash = false;
+            self.update_widths();
+            self.message = Some("Not enough room for full hashes".to_string());
+            return self.write_footer(stdout);
+        }
+
+        self.update_rows(stdout)
+    }
+
+    /// Characters of each hash that are shown, all of them while full hashes are on.
+    fn hash_shown(&self) -> usize {
+        match self.full_hash {
+            true => FULL_HASH,
+            false => self.hash_chars,
+        }
+    }
+
+    fn size_units(&self) -> Option<Units> {
+        (!self.bytes).then_some(self.units)
+    }
+
+    /// How the cells are written with the current settings.
+    fn cells(&self) -> Cells<'_> {
+        Cells {
+            units: self.size_units(),
+            hash_chars: self.hash_shown(),
+            time_format: &self.time_format,
+            icons: self.icons,
+            unicode: self.unicode,
+            hidden: &self.hidden,
+            duplicates: &self.duplicates,
+        }
+    }
+
+    /// Works the column widths out again for the listing and settings there are now.
+    fn update_widths(&mut self) {
+        let statuses = self
+            .files
+            .iter()
+            .any(|file| file.status != Status::None || file.local);
+        let room = row_room(self.prefix(), self.parts, self.size.0);
+        let cells = self.cells();
+        let widths = widths(&self.files, &cells, statuses, room);
+
+        // the status column is drawn next to the text rather than being part of it
+        let w = self
+            .files
+            .first()
+            .map_or(EMPTY_WIDTH, |f| row_cells(f, &widths, &cells).width());
+        self.w = match statuses {
+            true => w + COL_SPACING as usize + widths.status,
+            false => w,
+        };
+        self.widths = widths;
+    }
+
+    /// Index of the entry called `name`, the entries being in name order.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.files
+            .binary_search_by(|f| f.name.as_str().cmp(name))
+            .ok()
+    }
+
+    fn find_match(&self, query: &str) -> Option<usize> {
+        self.find_matches(query).first().copied()
+    }
+
+    fn find_matches(&self, query: &str) -> Vec<usize> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        // match against the raw names, not the padded row text
+        let query = query.to_lowercase();
+        self.visible
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| self.files[**e].name.to_lowercase().contains(&query))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn cycle_match(
+        &mut self,
+        stdout: &mut RawOut,
+        direction: Direction,
+    ) -> Result<(), Box<dyn Error>> {
+        let matches = match &self.search {
+            Some((_, matches)) if !matches.is_empty() => matches,
+            Some((query, _)) => {
+                self.message = Some(format!("No match for {}", query));
+                return Ok(());
+            }
+            None => {
+                self.message = Some("No previous search".to_string());
+                return Ok(());
+            }
+        };
+
+        // the nearest match past the pointer, wrapping around the list
+        let index = match direction {
+            Direction::Down => matches
+                .iter()
+                .find(|i| **i > self.index)
+                .unwrap_or(&matches[0]),
+            Direction::Up => matches
+                .iter()
+                .rev()
+                .find(|i| **i < self.index)
+                .unwrap_or(&matches[matches.len() - 1]),
+        };
+
+        self.jump_pointer(stdout, *index)?;
+        self.write_match_position();
+
+        Ok(())
+    }
+
+    fn cycle_selected(
+        &mut self,
+        stdout: &mut RawOut,
+        direction: Direction,
+    ) -> Result<(), Box<dyn Error>> {
+        let selected: Vec<usize> = (0..self.visible.len())
+            .filter(|i| self.files[self.visible[*i]].selected)
+            .collect();
+        if selected.is_empty() {
+            self.message = Some("Nothing selected".to_string());
+            return Ok(());
+        }
+
+        // the nearest selected row past the pointer, wrapping around the list
+        let index = match direction {
+            Direction::Down => selected
+                .iter()
+                .find(|i| **i > self.index)
+                .unwrap_or(&selected[0]),
+            Direction::Up => selected
+                .iter()
+                .rev()
+                .find(|i| **i < self.index)
+                .unwrap_or(&selected[selected.len() - 1]),
+        };
+
+        self.jump_pointer(stdout, *index)
+    }
+
+    fn write_match_position(&mut self) {
+        self.message = match &self.search {
+            Some((query, matches)) => match matches.iter().position(|i| *i == self.index) {
+                Some(pos) => Some(format!("match {}/{} for {}", pos + 1, matches.len(), query)),
+                None => Some(format!("No match for {}", query)),
+            },
+            None => None,
+        };
+    }
+
+    fn copy_hash(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let e = match self.visible.get(self.index) {
+            Some(e) => *e,
+            None => return Ok(()),
+        };
+
+        let hash = &self.files[e].hash;
+        if hash.is_empty() || hash == PENDING_HASH {
+            self.message = Some("no hash to copy".to_string());
+            return Ok(());
+        }
+        match osc52(stdout, hash) {
+            Ok(()) => self.message = Some("hash copied".to_string()),
+            Err(e) => self.report(format!("couldn't copy the hash: {}", e)),
+        }
+
+        Ok(())
+    }
+
+    fn set_mark(&mut self, key: Option<Key>) {
+        let e = match self.visible.get(self.index) {
+            Some(e) => *e,
+            None => return,
+        };
+
+        match key {
+            Some(Key::Char(c)) if c.is_ascii_alphabetic() => {
+                self.marks.insert(c, self.files[e].name.clone());
+            }
+            key => {
+                self.message = Some(format!(
+                    "Not a mark letter: {}",
+                    key.map(keymap::key_name).unwrap_or_default()
+                ))
+            }
+        }
+    }
+
+    fn jump_mark(&mut self, stdout: &mut RawOut, key: Option<Key>) -> Result<(), Box<dyn Error>> {
+        let c = match key {
+            Some(Key::Char(c)) if c.is_ascii_alphabetic() => c,
+            key => {
+                self.message = Some(format!(
+                    "Not a mark letter: {}",
+                    key.map(keymap::key_name).unwrap_or_default()
+                ));
+                return Ok(());
+            }
+        };
+
+        let name = match self.marks.get(&c) {
+            Some(name) => name,
+            None => {
+                self.message = Some(format!("Mark '{}' isn't set", c));
+                return Ok(());
+            }
+        };
+
+        let row = self
+            .visible
+            .iter()
+            .position(|e| self.files[*e].name == *name);
+        match row {
+            Some(row) => self.jump_pointer(stdout, row)?,
+            None => self.message = Some(format!("Mark '{}' is hidden by the filter", c)),
+        }
+
+        Ok(())
+    }
+
+    fn run_command(&mut self, stdout: &mut RawOut, cmd: &str) -> Result<(), Box<dyn Error>> {
+        if cmd.is_empty() {
+            return Ok(());
+        }
+
+        // out of range numbers clamp to the ends of the list
+        if let Ok(row) = cmd.parse::<usize>() {
+            return self.jump_pointer(stdout, min(max(row, 1), self.n).saturating_sub(1));
+        }
+
+        let (name, arg) = match cmd.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (cmd, ""),
+        };
+
+        match (name, arg) {
+            ("q" | "quit", "") if self.download.is_some() => self.mode = Mode::ConfirmQuit,
+            ("q" | "quit" | "q!" | "quit!", "") => self.quit = true,
+            ("sort", arg) => self.sort_command(stdout, arg)?,
+            ("filter", query) => {
+                self.filter = (!query.is_empty()).then(|| query.to_string());
+                self.update_rows(stdout)?;
+            }
+            ("clear", "") => self.select_all(stdout, false)?,
+            ("select", pattern) if !pattern.is_empty() => {
+                self.select_pattern(stdout, pattern, true)?
+            }
+            ("deselect", pattern) if !pattern.is_empty() => {
+                self.select_pattern(stdout, pattern, false)?
+            }
+            ("columns", arg) => self.columns_command(stdout, arg)?,
+            ("dedupe", "") => self.dedupe(stdout)?,
+            _ => self.message = Some(format!("Unknown command: {}", cmd)),
+        }
+
+        Ok(())
+    }
+
+    fn select_pattern(
+        &mut self,
+        stdout: &mut RawOut,
+        pattern: &str,
+        on: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        // raw names rather than the padded row text, and only what's shown so nothing out of
+        // sight ends up downloaded
+        let matches: Vec<usize> = self
+            .visible
+            .iter()
+            .copied()
+            .filter(|e| pattern::select_match(pattern, &self.files[*e].name))
+            .collect();
+        let hidden = (0..self.files.len())
+            .filter(|e| pattern::select_match(pattern, &self.files[*e].name))
+            .count()
+            - matches.len();
+        if matches.is_empty() {
+            self.message = Some(match hidden {
+                0 => format!("Nothing matches {}", pattern),
+                _ => format!("Only hidden entries match {}", pattern),
+            });
+            return Ok(());
+        }
+
+        let before = self.selection();
+        let changed = matches
+            .iter()
+            .filter(|e| self.files[**e].selected != on)
+            .count();
+        for e in matches {
+            self.files[e].selected = on;
+        }
+
+        let hidden = match hidden {
+            0 => String::new(),
+            1 => ", 1 hidden match left out".to_string(),
+            n => format!(", {} hidden matches left out", n),
+        };
+        self.message = Some(format!(
+            "{} {} {}{}",
+            match on {
+                true => "Selected",
+                false => "Deselected",
+            },
+            changed,
+            match changed {
+                1 => "entry",
+                _ => "entries",
+            },
+            hidden
+        ));
+        // an overwrite warning takes the place of the count
+        self.record(before);
+        self.write_rows(stdout)?;
+
+        Ok(())
+    }
+
+    /// Deselects all but the first selected copy of each group of entries with the same hash.
+    fn dedupe(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let before = self.selection();
+        let mut changed = 0;
+        for group in self.duplicates.values() {
+            // groups are in name order, so the copy that stays doesn't depend on the sort
+            let selected = group
+                .iter()
+                .filter_map(|name| self.position(name))
+                .filter(|e| self.files[*e].selected)
+                .skip(1);
+            for e in selected.collect::<Vec<_>>() {
+                self.files[e].selected = false;
+                changed += 1;
+            }
+        }
+
+        self.message = Some(match changed {
+            0 => "No selected duplicates".to_string(),
+            1 => "Deselected 1 duplicate".to_string(),
+            n => format!("Deselected {} duplicates", n),
+        });
+        self.record(before);
+        self.write_rows(stdout)?;
+
+        Ok(())
+    }
+
+    fn columns_command(&mut self, stdout: &mut RawOut, arg: &str) -> Result<(), Box<dyn Error>> {
+        // no argument brings every column back
+        let columns = match arg.is_empty() {
+            true => Ok(SortKey::ALL.to_vec()),
+            false => arg
+                .split(',')
+                .map(|name| column_name(name.trim()))
+                .collect::<Result<Vec<_>, _>>(),
+        };
+        let result = columns.and_then(|columns| self.set_columns(&columns));
+        if let Err(e) = result {
+            self.message = Some(e);
+            return Ok(());
+        }
+
+        // the pointer stays on its entry, update_rows keeps it there
+        self.update_rows(stdout)
+    }
+
+    /// Shows only `columns`, refusing to leave nothing to show.
+    fn set_columns(&mut self, columns: &[SortKey]) -> Result<(), String> {
+        let shown = |key: &SortKey| *key != SortKey::Hash || self.hash_chars > 0;
+        if !columns.iter().any(shown) {
+            return Err("Can't hide every column".to_string());
+        }
+
+        self.hidden = SortKey::ALL
+            .into_iter()
+            .filter(|key| !columns.contains(key))
+            .collect();
+        self.update_widths();
+
+        Ok(())
+    }
+
+    /// Sorts by the column after the current one.
+    fn next_sort(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        self.sort = Some(self.sort.map_or(SortKey::Name, SortKey::next));
+        self.sort_entries();
+        self.update_rows(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn reverse_sort(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        self.sort = Some(self.sort.unwrap_or(SortKey::Name));
+        self.reverse = !self.reverse;
+        self.sort_entries();
+        self.update_rows(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn sort_command(&mut self, stdout: &mut RawOut, arg: &str) -> Result<(), Box<dyn Error>> {
+        let mut words = arg.split_whitespace();
+        let key = match words.next() {
+            None => self.sort.map_or(SortKey::Name, SortKey::next),
+            Some(name) => match SortKey::from_name(name) {
+                Some(key) => key,
+                None => {
+                    self.message = Some(format!("Can't sort by {}", name));
+                    return Ok(());
+                }
+            },
+        };
+        let reverse = match words.next() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                self.message = Some(format!("Not a sort order: {}", other));
+                return Ok(());
+            }
+        };
+
+        self.sort = Some(key);
+        self.reverse = reverse;
+        self.sort_entries();
+        self.update_rows(stdout)
+    }
+
+    fn write_count(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let mut count = match self.count {
+            Some(count) => count.to_string(),
+            None => String::new(),
+        };
+        // keys of a pending sequence show up next to the count
+        if let Some((keys, _)) = &self.pending {
+            count.push_str(&keymap::sequence_name(keys));
+        }
+
+        // nothing to show, the footer line was already redrawn without it
+        if count.is_empty() {
+            return Ok(());
+        }
+
+        // right edge of the footer, padded so the footer text doesn't run into it
+        let pos = (
+            self.lay.footer.0 + self.w.saturating_sub(COUNT_WIDTH) as u16,
+            self.lay.footer.1,
+        );
+        let text = format!(
+            "{}{:>width$}",
+            self.theme.footer,
+            count,
+            width = COUNT_WIDTH
+        );
+        self.write_line(stdout, &pos, text)?;
+
+        Ok(())
+    }
+
+    fn half_page(&self) -> usize {
+        // half of the rows actually on screen, at least one
+        max(min(self.lay.page, self.n) / 2, 1)
+    }
+
+    fn row_at(&self, x: u16, y: u16) -> Option<usize> {
+        // rows span the checkbox prefix and the rendered columns
+        let cols = self.lay.list.0..self.lay.list.0 + (self.prefix() + self.w) as u16;
+        if !cols.contains(&x) || y < self.lay.list.1 {
+            return None;
+        }
+
+        let index = self.offset + (y - self.lay.list.1) as usize;
+        (index < self.n && self.row_pos(index).is_some()).then_some(index)
+    }
+
+    fn row_pos(&self, index: usize) -> Option<(u16, u16)> {
+        // rows scrolled out of the viewport aren't drawn
+        (self.offset..self.offset + self.lay.page)
+            .contains(&index)
+            .then(|| {
+                (
+                    self.lay.list.0,
+                    self.lay.list.1 + (index - self.offset) as u16,
+                )
+            })
+    }
+
+    /// Underlines the row at `x`, `y` instead of the one before, none if it isn't over a row.
+    fn set_hover(&mut self, stdout: &mut RawOut, x: u16, y: u16) -> Result<(), Box<dyn Error>> {
+        // popups cover the rows
+        let hover = match self.mode {
+            Mode::Help | Mode::Detail => None,
+            _ => self.row_at(x, y).map(|_| y),
+        };
+        let prev = mem::replace(&mut self.hover, hover);
+        if prev == hover {
+            return Ok(());
+        }
+
+        for y in [prev, hover].into_iter().flatten() {
+            if let Some(index) = self.row_at(self.lay.list.0, y) {
+                self.paint_row(stdout, index)?;
+            }
+        }
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn click(&mut self, stdout: &mut RawOut, index: usize, x: u16) -> Result<(), Box<dyn Error>> {
+        // the checkbox cells toggle the row without moving the pointer
+        let w = checkbox_width(&self.checkbox) as u16;
+        let checkbox = self.lay.list.0 + self.prefix() as u16 - w - 1;
+        if (checkbox..checkbox + w).contains(&x) {
+            self.last_click = None;
+            return self.toggle(stdout, index);
+        }
+
+        let double = matches!(
+            self.last_click,
+            Some((i, t)) if i == index && t.elapsed() < DOUBLE_CLICK
+        );
+
+        if double {
+            self.last_click = None;
+            self.toggle(stdout, index)
+        } else {
+            self.last_click = Some((index, Instant::now()));
+            self.jump_pointer(stdout, index)
+        }
+    }
+
+    fn toggle(&mut self, stdout: &mut RawOut, index: usize) -> Result<(), Box<dyn Error>> {
+        let e = match self.visible.get(index) {
+            Some(e) => *e,
+            None => return Ok(()),
+        };
+
+        let before = self.selection();
+        self.files[e].selected = !self.files[e].selected;
+        self.record(before);
+        self.paint_row(stdout, index)?;
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn toggle_advance(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        if self.n == 0 {
+            return Ok(());
+        }
+
+        let prev = self.index;
+        let e = self.visible[prev];
+        let before = self.selection();
+        self.files[e].selected = !self.files[e].selected;
+        self.record(before);
+        // both rows and the counter go out with one flush
+        match self.place_pointer(min(prev + 1, self.n - 1)) {
+            true => self.write_rows(stdout)?,
+            false => {
+                self.paint_row(stdout, prev)?;
+                self.set_pointer(stdout)?;
+            }
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn select_all(&mut self, stdout: &mut RawOut, selected: bool) -> Result<(), Box<dyn Error>> {
+        // only the shown entries, hidden selections stay as they are
+        let before = self.selection();
+        for e in &self.visible {
+            self.files[*e].selected = selected;
+        }
+        self.record(before);
+        self.write_rows(stdout)?;
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn invert_selection(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let before = self.selection();
+        for e in &self.visible {
+            self.files[*e].selected = !self.files[*e].selected;
+        }
+        self.record(before);
+        self.write_rows(stdout)?;
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Remembers the selection from before a change so it can be undone.
+    fn record(&mut self, before: Vec<bool>) {
+        // selecting what's already selected isn't worth an undo step
+        if before == self.selection() {
+            return;
+        }
+        self.warn_local(&before);
+
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before);
+        self.redo.clear();
+    }
+
+    /// Warns when the change selected files that downloading would overwrite.
+    fn warn_local(&mut self, before: &[bool]) {
+        let overwritten: Vec<usize> = (0..self.files.len())
+            .filter(|e| !before[*e] && self.files[*e].selected && self.files[*e].local)
+            .collect();
+
+        self.message = match overwritten.as_slice() {
+            [] => return,
+            [e] => Some(format!(
+                "{} is already in {}, downloading it overwrites that copy",
+                self.files[*e].name,
+                self.dest.display()
+            )),
+            es => Some(format!(
+                "{} of these are already in {}, downloading them overwrites those copies",
+                es.len(),
+                self.dest.display()
+            )),
+        };
+    }
+
+    fn selection(&self) -> Vec<bool> {
+        self.files.iter().map(|d| d.selected).collect()
+    }
+
+    fn undo(&mut self, stdout: &mut RawOut, times: usize) -> Result<(), Box<dyn Error>> {
+        if self.undo.is_empty() {
+            self.message = Some("Nothing to undo".to_string());
+        }
+
+        for _ in 0..times {
+            let prev = match self.undo.pop_back() {
+                Some(prev) => prev,
+                None => break,
+            };
+            self.redo.push(self.selection());
+            self.restore(stdout, prev)?;
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn redo(&mut self, stdout: &mut RawOut, times: usize) -> Result<(), Box<dyn Error>> {
+        if self.redo.is_empty() {
+            self.message = Some("Nothing to redo".to_string());
+        }
+
+        for _ in 0..times {
+            let next = match self.redo.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            self.undo.push_back(self.selection());
+            self.restore(stdout, next)?;
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn restore(&mut self, stdout: &mut RawOut, selection: Vec<bool>) -> Result<(), Box<dyn Error>> {
+        // only rows whose checkbox actually changes get repainted
+        let changed: Vec<usize> = (0..self.n)
+            .filter(|i| self.files[self.visible[*i]].selected != selection[self.visible[*i]])
+            .collect();
+
+        for (d, selected) in self.files.iter_mut().zip(selection) {
+            d.selected = selected;
+        }
+        for i in changed {
+            self.paint_row(stdout, i)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_rows(&self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        if self.n == 0 {
+            let text = match self.files.is_empty() {
+                true => "no files available",
+                false => "no matches",
+            };
+            // in the middle of where the rows would be
+            let room = self.prefix() + self.w;
+            let x = self.lay.list.0 + room.saturating_sub(text.width()) as u16 / 2;
+            let y = self.lay.list.1;
+            self.write_line(stdout, &(1, y), clear::CurrentLine.to_string())?;
+            self.write_line(stdout, &(x, y), format!("{}{}", self.theme.list, text))?;
+            self.restore_line(stdout, self.lay.list.1)?;
+            self.write_pane(stdout)?;
+        }
+
+        for i in self.offset..min(self.offset + self.lay.page, self.n) {
+            self.paint_row(stdout, i)?;
+        }
+
+        Ok(())
+    }
+
+    fn paint_row(&self, stdout: &mut RawOut, index: usize) -> Result<(), Box<dyn Error>> {
+        match index == self.index {
+            true => self.set_pointer(stdout),
+            false => self.clear_pointer(stdout, index),
+        }
+    }
+
+    fn jump_pointer(&mut self, stdout: &mut RawOut, index: usize) -> Result<(), Box<dyn Error>> {
+        if self.n == 0 || index == self.index {
+            return Ok(());
+        }
+
+        let prev = self.index;
+        let scrolled = self.place_pointer(index);
+
+        // repaint with a single flush, a visual range changes every row in between
+        match self.mode {
+            _ if scrolled => self.write_rows(stdout)?,
+            Mode::Visual(_) => {
+                for i in min(prev, index)..=max(prev, index) {
+                    self.paint_row(stdout, i)?;
+                }
+            }
+            _ => {
+                self.clear_pointer(stdout, prev)?;
+                self.set_pointer(stdout)?;
+            }
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Moves the pointer to `index`, returning whether the viewport had to scroll to show it.
+    fn place_pointer(&mut self, index: usize) -> bool {
+        let (offset, page) = (self.offset, self.lay.page);
+        self.index = index;
+
+        // the margin gives way at the ends of the list
+        let margin = self.margin();
+        let last = self.n.saturating_sub(page);
+        let lowest = min((index + margin + 1).saturating_sub(page), last);
+        let highest = min(index.saturating_sub(margin), last);
+        self.offset = max(min(offset, highest), lowest);
+        self.pointer = (
+            self.lay.list.0,
+            self.lay.list.1 + (index - self.offset) as u16,
+        );
+
+        self.offset != offset
+    }
+
+    fn margin(&self) -> usize {
+        // a short page can't keep the full margin on both sides
+        min(self.scrolloff, self.lay.page.saturating_sub(1) / 2)
+    }
+
+    /// Scrolls the list to start at row `offset` without moving the pointer off its entry.
+    fn scroll(&mut self, stdout: &mut RawOut, offset: usize) -> Result<(), Box<dyn Error>> {
+        // clamped so the end of the list doesn't leave blank rows
+        let offset = min(offset, self.n.saturating_sub(self.lay.page));
+        if offset == self.offset {
+            return Ok(());
+        }
+
+        self.offset = offset;
+        self.place_pointer(self.index);
+        self.write_rows(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn visual_range(&self) -> Option<RangeInclusive<usize>> {
+        match self.mode {
+            Mode::Visual(anchor) => Some(min(anchor, self.index)..=max(anchor, self.index)),
+            _ => None,
+        }
+    }
+
+    fn end_visual(&mut self, stdout: &mut RawOut, apply: bool) -> Result<(), Box<dyn Error>> {
+        let range = match self.visual_range() {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        if apply {
+            let before = self.selection();
+            for e in &self.visible[range.clone()] {
+                self.files[*e].selected = !self.files[*e].selected;
+            }
+            self.record(before);
+        }
+
+        self.mode = Mode::Normal;
+        for i in range {
+            self.paint_row(stdout, i)?;
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Names and sizes of the selected files in name order, selections hidden by the filter
+    /// included.
+    fn selected_files(&self) -> Vec<(String, Option<u64>)> {
+        self.files
+            .iter()
+            .filter(|file| file.selected)
+            .map(|file| (file.name.clone(), file.size))
+            .collect()
+    }
+
+    fn init_dl(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        // selections hidden by the filter are still downloaded
+        let files = self.selected_files();
+
+        if files.is_empty() {
+            let hint = match self.keymap.keys(Action::Toggle).first() {
+                Some(key) => format!(" — press {} to select files", keymap::sequence_name(key)),
+                None => String::new(),
+            };
+            self.message = Some(format!("Nothing selected{}", hint));
+            self.write_footer(stdout)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+
+        // selections out of view are easy to forget, so they're pointed out
+        let hidden = (0..self.files.len())
+            .filter(|e| self.files[*e].selected && !self.visible.contains(e))
+            .count();
+        if hidden > 0 {
+            let files = match hidden {
+                1 => "file that isn't",
+                _ => "files that aren't",
+            };
+            self.message = Some(format!(
+                "Also downloading {} selected {} shown",
+                hidden, files
+            ));
+        }
+
+        // results of an earlier download make way for the new queue
+        for file in &mut self.files {
+            file.status = match file.selected {
+                true => Status::Queued,
+                false => Status::None,
+            };
+        }
+
+        self.update_widths();
+        self.update_rows(stdout)?;
+        stdout.flush()?;
+
+        let count = files.len();
+        let total = files.iter().filter_map(|(_, size)| *size).sum();
+        let unknown = files.iter().any(|(_, size)| size.is_none());
+        let (dl_tx, dl_rx) = mpsc::channel::<Transfer>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let worker_cancel = Arc::clone(&cancel);
+        let faults = self.mock_faults;
+        thread::spawn(move || {
+            // the worker's own errors end the download instead of vanishing with the thread
+            if let Err(e) = mock(&files, faults, &worker_cancel, dl_tx.clone()) {
+                dl_tx.send(Transfer::Error(e.to_string())).ok();
+            }
+        });
+
+        self.download = Some(Download {
+            updates: dl_rx,
+            cancel,
+            files: count,
+            total,
+            unknown,
+            arrived: 0,
+            started: Instant::now(),
+            frame: 0,
+            ticked: Instant::now(),
+            samples: VecDeque::new(),
+            rate: None,
+        });
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn poll_dl(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let updates: Vec<Transfer> = match &self.download {
+            Some(dl) => dl.updates.try_iter().collect(),
+            None => return Ok(()),
+        };
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        for update in updates {
+            let (name, status) = match update {
+                Transfer::Progress(name, done, total) => (name, Status::Transferring(done, total)),
+                Transfer::Done(name) => {
+                    self.count_arrived(&name);
+                    (name, Status::Done)
+                }
+                Transfer::Failed(name, reason) => (name, Status::Failed(reason)),
+                Transfer::Error(e) => return self.fail_dl(stdout, &e),
+                Transfer::Finished => return self.finish_dl(stdout),
+            };
+            self.set_status(stdout, &name, status)?;
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn tick_spinner(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        match &mut self.download {
+            Some(dl) if dl.ticked.elapsed() >= SPINNER_TICK => {
+                dl.frame += 1;
+                dl.ticked = Instant::now();
+            }
+            _ => return Ok(()),
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn set_status(
+        &mut self,
+        stdout: &mut RawOut,
+        name: &str,
+        status: Status,
+    ) -> Result<(), Box<dyn Error>> {
+        let e = match self.position(name) {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+        self.files[e].status = status;
+
+        // only the row itself changes, and only if it's shown
+        if let Some(index) = self.visible.iter().position(|v| *v == e) {
+            self.paint_row(stdout, index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps what arrived of a file without a size before its row forgets it.
+    fn count_arrived(&mut self, name: &str) {
+        let arrived = match self.position(name) {
+            Some(e) => match self.files[e].status {
+                Status::Transferring(done, None) => done,
+                _ => return,
+            },
+            None => return,
+        };
+        if let Some(dl) = &mut self.download {
+            dl.arrived += arrived;
+        }
+    }
+
+    fn finish_dl(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let (_, done) = self.download_done();
+        let dl = match self.download.take() {
+            Some(dl) => dl,
+            None => return Ok(()),
+        };
+        let failed = self
+            .files
+            .iter()
+            .filter(|file| matches!(file.status, Status::Failed(_)))
+            .count();
+        let files = match dl.files - failed {
+            1 => "1 file".to_string(),
+            n => format!("{} files", n),
+        };
+        let failed = match failed {
+            0 => String::new(),
+            n => format!(", {} failed", n),
+        };
+        self.message = Some(format!(
+            "{}, {} in {}{}",
+            files,
+            self.size_text(done),
+            elapsed_text(dl.started.elapsed()),
+            failed
+        ));
+
+        // a finished download leaves the prompt, since there's nothing left to abandon
+        if self.mode == Mode::ConfirmQuit {
+            self.mode = Mode::Normal;
+        }
+
+        // what just arrived is local now
+        match self.scan_local() {
+            true => self.update_rows(stdout)?,
+            false => self.write_footer(stdout)?,
+        }
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn fail_dl(&mut self, stdout: &mut RawOut, reason: &str) -> Result<(), Box<dyn Error>> {
+        let dl = match self.download.take() {
+            Some(dl) => dl,
+            None => return Ok(()),
+        };
+
+        dl.cancel.store(true, Ordering::Relaxed);
+        self.report(format!("download stopped: {}", reason));
+
+        // whatever hadn't arrived yet is lost, shown or not
+        for e in 0..self.files.len() {
+            if let Status::Queued | Status::Transferring(..) = self.files[e].status {
+                self.files[e].status = Status::Failed(reason.to_string());
+                if let Some(index) = self.visible.iter().position(|v| *v == e) {
+                    self.paint_row(stdout, index)?;
+                }
+            }
+        }
+        if self.mode == Mode::ConfirmQuit {
+            self.mode = Mode::Normal;
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn cancel_dl(&mut self, stdout: &mut RawOut) -> Result<(), Box<dyn Error>> {
+        let dl = match self.download.take() {
+            Some(dl) => dl,
+            None => return Ok(()),
+        };
+
+        dl.cancel.store(true, Ordering::Relaxed);
+        self.message = Some("Download cancelled".to_string());
+
+        // files that were still to come won't arrive now, shown or not
+        for e in 0..self.files.len() {
+            if let Status::Queued | Status::Transferring(..) = self.files[e].status {
+                self.files[e].status = Status::None;
+                if let Some(index) = self.visible.iter().position(|v| *v == e) {
+                    self.paint_row(stdout, index)?;
+                }
+            }
+        }
+        self.write_footer(stdout)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+}
+
+fn osc52(stdout: &mut RawOut, text: &str) -> Result<(), Box<dyn Error>> {
+    // the linux console and dumb terminals print the sequence instead of handling it
+    let term = env::var("TERM").unwrap_or_default();
+    if term.is_empty() || term == "dumb" || term.starts_with("linux") {
+        return Err(format!("clipboard escapes aren't supported by {:?}", term).into());
+    }
+
+    // OSC 52 hands the text to the terminal, which also works over ssh
+    write!(stdout, "\x1B]52;c;{}\x07", STANDARD.encode(text))?;
+
+    Ok(())
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(width).map(|c| c.iter().collect()).collect()
+}
+
+fn unicode_locale() -> bool {
+    // the first of these that is set wins, as with setlocale
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|v| !v.is_empty())
+        .is_some_and(|v| {
+            let v = v.to_lowercase();
+            v.contains("utf-8") || v.contains("utf8")
+        })
+}
+
+fn is_wheel(e: &Event) -> bool {
+    matches!(
+        e,
+        Event::Mouse(MouseEvent::Press(
+            MouseButton::WheelUp | MouseButton::WheelDown,
+            ..
+        ))
+    )
+}
+
+fn is_motion(e: &Event) -> bool {
+    matches!(e, Event::Mouse(MouseEvent::Hold(..)))
+}
+
+fn wheel_delta(button: MouseButton) -> isize {
+    match button {
+        MouseButton::WheelUp => -1,
+        _ => 1,
+    }
+}
+
+fn rand_string(limit: Option<usize>) -> String {
+    let len = match limit {
+        Some(limit) => limit,
+        None => rand::thread_rng().gen_range(5..30),
+    };
+    Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+}
+
+fn widths(files: &[FileEntry], cells: &Cells, statuses: bool, room: usize) -> Widths {
+    let mut widths = Widths::default();
+
+    // columns are left out entirely when they're not shown
+    for key in SortKey::ALL.into_iter().filter(|key| cells.shown(*key)) {
+        *widths.column(key) = files
+            .iter()
+            .map(|file| cells.text(key, file).width())
+            .max()
+            .unwrap_or(0);
+    }
+    let status = match statuses {
+        true => STATUS_WIDTH,
+        false => 0,
+    };
+
+    let others = |widths: &Widths, status: usize| -> usize {
+        [widths.kind, widths.size, widths.hash, widths.time, status]
+            .iter()
+            .filter(|w| **w > 0)
+            .map(|w| w + COL_SPACING as usize)
+            .sum()
+    };
+
+    // when even the shortest names don't fit, the hash goes first, then the time, the type
+    // and the size
+    let min_name = min(widths.name, MIN_NAME_WIDTH);
+    let min_status = min(status, MIN_STATUS_WIDTH);
+    for key in [SortKey::Hash, SortKey::Modified, SortKey::Type, SortKey::Size] {
+        if room < min_name + others(&widths, min_status) {
+            *widths.column(key) = 0;
+        }
+    }
+
+    // names get whatever the other columns leave over, long ones are cut short
+    let others = others(&widths, status);
+    widths.name = min(widths.name, max(room.saturating_sub(others), MIN_NAME_WIDTH));
+
+    // past that the progress bars give up their room too
+    if statuses {
+        let left = room.saturating_sub(others - status + widths.name);
+        widths.status = left.clamp(MIN_STATUS_WIDTH, STATUS_WIDTH);
+    }
+
+    widths
+}
+
+/// The text of a file's row, each cell padded to its column's width.
+fn row_cells(file: &FileEntry, widths: &Widths, cells: &Cells) -> String {
+    // columns that don't fit are dropped with a width of 0
+    let columns: Vec<String> = SortKey::ALL
+        .into_iter()
+        .filter(|key| widths.get(*key) > 0)
+        .map(|key| {
+            let w = widths.get(key);
+            let text = match key {
+                SortKey::Name => ellipsize(&file.name, w),
+                key => cells.text(key, file),
+            };
+
+            // padded by terminal cells, wide and combining characters don't take one each
+            let pad = " ".repeat(w.saturating_sub(text.width()));
+            match key {
+                SortKey::Size => format!("{}{}", pad, text),
+                _ => format!("{}{}", text, pad),
+            }
+        })
+        .collect();
+
+    columns.join(COL_SEPARATOR)
+}
+
+fn ellipsize(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+
+    // whole graphemes only, so accents stay on their letters
+    let mut cut = String::new();
+    for g in text.graphemes(true) {
+        if cut.width() + g.width() + 1 > width {
+            break;
+        }
+        cut.push_str(g);
+    }
+    cut.push('…');
+
+    cut
+}
+
+/// A bar like `[####....]` of `width` cells, filled as far as `done` is through `total`.
+fn progress_bar(done: u64, total: u64, width: usize, empty: char) -> String {
+    let filled = min((width as u64 * done / max(total, 1)) as usize, width);
+    format!(
+        "[{}{}]",
+        "#".repeat(filled),
+        empty.to_string().repeat(width - filled)
+    )
+}
+
+fn elapsed_text(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        _ => format!("{}m {:02}s", secs / 60, secs % 60),
+    }
+}
+
+fn eta_text(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        0..=3599 => format!("{}:{:02}", secs / 60, secs % 60),
+        _ => format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+fn row_room(prefix: usize, parts: Parts, width: u16) -> usize {
+    // the terminal width less the row prefix, the scrollbar, the side pane and some margin
+    let frame = match parts.frame {
+        true => 4,
+        false => 0,
+    };
+    let pane = pane_width(parts.pane, width) as usize;
+    (width as usize).saturating_sub(prefix + frame + pane + 10)
+}
+
+/// Width of the side pane on a terminal `width` cells wide, 0 when it's off or doesn't fit.
+fn pane_width(pane: bool, width: u16) -> u16 {
+    match pane && width >= PANE_MIN_TERMINAL {
+        true => min(width / 3, MAX_PANE_WIDTH),
+        false => 0,
+    }
+}
+
+/// Names and sizes of the files in `dir`, nothing if it can't be read.
+fn local_files(dir: &Path) -> HashMap<String, u64> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return HashMap::new(),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.file_name().into_string().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+fn checkbox_width(glyphs: &(String, String)) -> usize {
+    max(glyphs.0.width(), glyphs.1.width())
+}
+
+fn number_width(shown: bool, count: usize) -> usize {
+    // the digits of the largest number and a space
+    match shown {
+        true => count.to_string().len() + 1,
+        false => 0,
+    }
+}
+
+fn kind_text(name: &str, icons: bool) -> String {
+    let extension = filetype::extension(name);
+    let text = extension.as_deref().unwrap_or("-");
+    match icons {
+        true => format!("{} {}", filetype::Kind::of(extension.as_deref()).icon(), text),
+        false => text.to_string(),
+    }
+}
+
+fn time_text(mtime: Option<i64>, format: &str) -> String {
+    // entries without a time, or one the format can't show, get a dash
+    mtime
+        .and_then(|t| date::format(t, format))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn hash_text(hash: &str, chars: usize) -> String {
+    // short hashes fit as they are, and listings without one get a dash like a missing time
+    if hash.is_empty() {
+        "-".to_string()
+    } else if hash.chars().count() > chars {
+        format!("{}...", hash.chars().take(chars).collect::<String>())
+    } else {
+        hash.to_string()
+    }
+}
+
+fn signal_handler(tx: Sender<Signal>) -> Result<(), Box<dyn Error>> {
+    // for contego's async context: tokio::signal::unix::{signal, SignalKind}
+    let mut signals = Signals::new([SIGWINCH, SIGTSTP, SIGCONT, SIGINT, SIGTERM, SIGHUP])?;
+
+    for signal in &mut signals {
+        match signal {
+            SIGWINCH => tx.send(Signal::Resize)?,
+            // hand the terminal back to the shell before actually stopping
+            SIGTSTP => {
+                terminal::restore();
+                low_level::emulate_default_handler(SIGTSTP)?;
+                // only returns once continued, or right away if the stop was ignored
+                tx.send(Signal::Resume)?;
+            }
+            SIGCONT => tx.send(Signal::Resume)?,
+            // killed, so the terminal has to be put back from here
+            _ => {
+                terminal::restore();
+                process::exit(128 + signal);
+            }
+        }
+        input::wake();
+    }
+
+    Ok(())
+}
+
+fn mock(
+    files: &[(String, Option<u64>)],
+    faults: bool,
+    cancel: &AtomicBool,
+    tx: Sender<Transfer>,
+) -> Result<(), Box<dyn Error>> {
+    // mock function for sending client requests, in chunks so a cancel stops it promptly
+    // and the receiver is gone once the download is cancelled, so sends may fail
+    'files: for (i, (name, size)) in files.iter().enumerate() {
+        for chunk in 1..=10 {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(100));
+
+            // with --mock-faults every tenth transfer breaks off, like a dropped connection would
+            if faults && chunk == 5 && i % 10 == 9 {
+                let reason = "connection reset by peer".to_string();
+                tx.send(Transfer::Failed(name.clone(), reason)).ok();
+                continue 'files;
+            }
+            // files without a size arrive like any other, just with no end in sight
+            let done = size.unwrap_or(MOCK_UNKNOWN_SIZE) * chunk / 10;
+            tx.send(Transfer::Progress(name.clone(), done, *size)).ok();
+        }
+        tx.send(Transfer::Done(name.clone())).ok();
+    }
+
+    tx.send(Transfer::Finished).ok();
+
+    Ok(())
+}
+
+fn mock_connection(_addr: &str, faults: bool, tx: Sender<Connection>) {
+    // mock connection to the server, which stays up unless --mock-faults has it drop once and
+    // come back, and then drop for good
+    tx.send(Connection::Connected).ok();
+    input::wake();
+    if !faults {
+        return;
+    }
+
+    let script = [
+        (10, Connection::Reconnecting),
+        (2, Connection::Connected),
+        (20, Connection::Reconnecting),
+        (2, Connection::Disconnected),
+    ];
+    for (secs, state) in script {
+        thread::sleep(Duration::from_secs(secs));
+        if tx.send(state).is_err() {
+            return;
+        }
+        input::wake();
+    }
+}
+
+fn mock_listing() -> Listing {
+    // mock file listing of the server, where a few files don't have a modified time
+    let mut rng = rand::thread_rng();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let mut data = BTreeMap::new();
+    let extensions = ["", ".iso", ".tar.gz", ".txt", ".zip", ".mp4", ".pdf"];
+    (0..20).for_each(|_| {
+        let extension = extensions[rng.gen_range(0..extensions.len())];
+        let filename = format!("{}{}", rand_string(None), extension);
+        let entry = Entry {
+            size: Some(rng.gen_range(100..1000000)),
+            hash: rand_string(Some(64)),
+            mtime: (!rng.gen_ratio(1, 10)).then(|| now - rng.gen_range(0..365 * 24 * 3600)),
+            error: None,
+        };
+
+        data.insert(filename, entry);
+    });
+
+    Ok(data)
+}
+
+#[cfg(feature = "url")]
+fn url_listing(url: &http::Url) -> Listing {
+    let body = http::get(url)?;
+
+    json_listing(&body).map_err(|e| format!("bad listing from {}: {}", url.host, e))
+}
+
+/// Names of the entries sharing each hash that's listed more than once, in name order.
+fn duplicate_groups(files: &[FileEntry]) -> HashMap<String, Vec<String>> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    // files without a digest yet or at all don't count as the same
+    for file in files {
+        if file.error.is_none() && !file.hash.is_empty() && file.hash != PENDING_HASH {
+            groups
+                .entry(file.hash.clone())
+                .or_default()
+                .push(file.name.clone());
+        }
+    }
+    groups.retain(|_, names| names.len() > 1);
+
+    groups
+}
+
+/// What the listing is, and how many of its files are shown when the globs left some out.
+fn listing_header(
+    server: Option<&str>,
+    source: &Source,
+    took: Duration,
+    shown: usize,
+    listed: usize,
+) -> String {
+    let header = match server {
+        Some(addr) => format!("Server {}", addr),
+        None => source.describe(took),
+    };
+
+    match shown == listed {
+        true => header,
+        false => format!("{} · showing {} of {} files", header, shown, listed),
+    }
+}
+
+/// The entries whose names pass the `only` and `exclude` globs, and how many were listed.
+fn narrow(
+    mut data: BTreeMap<String, Entry>,
+    only: &[String],
+    exclude: &[String],
+) -> (BTreeMap<String, Entry>, usize) {
+    let listed = data.len();
+    // any of the includes lets a name in, then any of the excludes takes it out again
+    data.retain(|name, _| {
+        (only.is_empty() || only.iter().any(|glob| pattern::glob_match(glob, name)))
+            && !exclude.iter().any(|glob| pattern::glob_match(glob, name))
+    });
+
+    (data, listed)
+}
+
+fn input_listing(path: &Path, delimiter: Option<char>) -> Listing {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let delimiter = delimiter.unwrap_or_else(|| csv::detect(&text));
+
+    table_listing(&text, delimiter).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The files in the directory given on the command line, with their digests left to be
+/// filled in. Links and other files that aren't regular get an error instead.
+fn local_listing(dir: &Path) -> Listing {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("can't read {}: {}", dir.display(), e))?;
+
+    let mut data = BTreeMap::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // the entry's own metadata, links aren't followed
+        let (size, mtime, error) = match entry.metadata() {
+            Ok(meta) if meta.is_dir() => continue,
+            Ok(meta) => {
+                let mtime = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs() as i64);
+                let error = match meta.file_type() {
+                    t if t.is_symlink() => Some("symlink".to_string()),
+                    t if !t.is_file() => Some("not a regular file".to_string()),
+                    _ => None,
+                };
+                (Some(meta.len()), mtime, error)
+            }
+            Err(e) => (None, None, Some(e.kind().to_string())),
+        };
+        let hash = match error {
+            Some(_) => String::new(),
+            None => PENDING_HASH.to_string(),
+        };
+
+        let entry = Entry {
+            size,
+            hash,
+            mtime,
+            error,
+        };
+        data.insert(name, entry);
+    }
+
+    Ok(data)
+}
+
+/// The entries of a delimited listing, rows of name, size and hash in that order unless a
+/// header row names the columns. Cells for the hash may be left empty or out.
+fn table_listing(text: &str, delimiter: char) -> Result<BTreeMap<String, Entry>, String> {
+    let mut records = csv::records(text, delimiter)?.into_iter().peekable();
+
+    // a first row with a name and a size column is a header, other columns in it are ignored
+    let named =
+        |fields: &[String], key: &str| fields.iter().position(|f| f.eq_ignore_ascii_case(key));
+    let columns: Vec<String> = match records.peek() {
+        Some((_, fields)) if named(fields, "name").is_some() && named(fields, "size").is_some() => {
+            records.next().map_or(Vec::new(), |(_, fields)| fields)
+        }
+        _ => vec!["name".to_string(), "size".to_string(), "hash".to_string()],
+    };
+    let (name_at, size_at) = match (named(&columns, "name"), named(&columns, "size")) {
+        (Some(name), Some(size)) => (name, size),
+        _ => return Err("the header has no name or no size column".to_string()),
+    };
+    let hash_at = named(&columns, "hash");
+
+    let mut data = BTreeMap::new();
+    for (line, fields) in records {
+        if fields.len() > columns.len() {
+            return Err(format!(
+                "line {}: expected at most {} fields, found {}",
+                line,
+                columns.len(),
+                fields.len()
+            ));
+        }
+        let cell = |i: usize| fields.get(i).map_or("", |f| f.as_str());
+
+        let name = cell(name_at);
+        if name.is_empty() {
+            return Err(format!("line {}: missing name", line));
+        }
+        // an empty size or a question mark is one the source doesn't know
+        let size = match cell(size_at) {
+            "" | "?" => None,
+            size => Some(size.parse::<u64>().map_err(|_| {
+                format!(
+                    "line {}: size '{}' isn't a whole number of bytes",
+                    line, size
+                )
+            })?),
+        };
+        if data.contains_key(name) {
+            return Err(format!("line {}: '{}' is listed twice", line, name));
+        }
+
+        let entry = Entry {
+            size,
+            hash: hash_at.map_or("", cell).to_string(),
+            mtime: None,
+            error: None,
+        };
+        data.insert(name.to_string(), entry);
+    }
+
+    Ok(data)
+}
+
+/// The entries of a JSON listing, an array of objects with a `name` and optionally a `size`
+/// in bytes, a `hash` and an `mtime` in seconds since the epoch.
+fn json_listing(text: &str) -> Result<BTreeMap<String, Entry>, String> {
+    let items = match json::parse(text)? {
+        json::Value::Array(items) => items,
+        value => {
+            return Err(format!(
+                "expected an array of files, found {}",
+                value.kind()
+            ))
+        }
+    };
+
+    let mut data = BTreeMap::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let fields = match item {
+            json::Value::Object(fields) => fields,
+            value => {
+                return Err(format!(
+                    "file {}: expected an object, found {}",
+                    i,
+                    value.kind()
+                ))
+            }
+        };
+
+        let (mut name, mut size, mut hash, mut mtime) = (None, None, String::new(), None);
+        for (key, value) in fields {
+            let wrong = |want: &str| format!("file {}: {} must be {}", i, key, want);
+            match (key.as_str(), value) {
+                ("name", json::Value::String(s)) if !s.is_empty() => name = Some(s),
+                ("name", _) => return Err(wrong("a non-empty string")),
+                ("size", json::Value::Number(n)) => {
+                    size = Some(
+                        n.parse::<u64>()
+                            .map_err(|_| wrong("a whole number of bytes"))?,
+                    )
+                }
+                ("size", json::Value::Null) => {}
+                ("size", _) => return Err(wrong("a whole number of bytes")),
+                ("hash", json::Value::String(s)) => hash = s,
+                ("hash", json::Value::Null) => {}
+                ("hash", _) => return Err(wrong("a string")),
+                ("mtime", json::Value::Number(n)) => {
+                    mtime = Some(n.parse::<i64>().map_err(|_| wrong("whole seconds"))?)
+                }
+                ("mtime", json::Value::Null) => {}
+                ("mtime", _) => return Err(wrong("whole seconds")),
+                (key, _) => return Err(format!("file {}: unknown field '{}'", i, key)),
+            }
+        }
+
+        let name = name.ok_or_else(|| format!("file {}: missing name", i))?;
+        if data.contains_key(&name) {
+            return Err(format!("file {}: '{}' is listed twice", i, name));
+        }
+        let entry = Entry {
+            size,
+            hash,
+            mtime,
+            error: None,
+        };
+        data.insert(name, entry);
+    }
+
+    Ok(data)
+}
+
+fn delimiter(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (s, chars.next(), chars.next()) {
+        ("tab", ..) => Ok('\t'),
+        (_, Some(c), None) if c != '"' && c != '\n' => Ok(c),
+        _ => Err("expected a single character or 'tab'".to_string()),
+    }
+}
+
+fn column_name(s: &str) -> Result<SortKey, String> {
+    SortKey::from_name(s)
+        .ok_or_else(|| format!("'{}' isn't a column, try name, type, size, hash or modified", s))
+}
+
+/// Checks a `--server` address has a host and a valid port.
+fn server_addr(s: &str) -> Result<String, String> {
+    let (host, port) = s
+        .rsplit_once(':')
+        .ok_or_else(|| "expected HOST:PORT".to_string())?;
+    if host.is_empty() {
+        return Err("missing host".to_string());
+    }
+    port.parse::<u16>()
+        .map_err(|_| format!("'{}' isn't a valid port", port))?;
+
+    Ok(s.to_string())
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // a broken config is reported before the terminal is taken over
+    let mut config = match config::load(args.keys) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("leightbox: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(n) = args.hash_chars {
+        config.hash_chars = n;
+    }
+    config.full_hash = args.full_hash;
+    config.mock_faults = args.mock_faults;
+    config.only = args.only;
+    config.exclude = args.exclude;
+    config.icons |= args.icons;
+    config.alt_screen &= !args.no_alt_screen;
+    // any non-empty NO_COLOR turns colors off unless they're asked for, see no-color.org
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    config.depth = match args.color {
+        _ if args.no_color => Depth::Monochrome,
+        ColorMode::Never => Depth::Monochrome,
+        ColorMode::Auto if no_color => Depth::Monochrome,
+        ColorMode::Auto => Depth::detect(),
+        // at least the basic colors, whatever the terminal claims
+        ColorMode::Always => match Depth::detect() {
+            Depth::Monochrome => Depth::Ansi8,
+            depth => depth,
+        },
+    };
+
+    // the listing is read in full, and checked, before the terminal is taken over
+    let source = match args.stdin_format {
+        Some(StdinFormat::Json) => {
+            let mut text = String::new();
+            let data = stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("can't read the listing: {}", e))
+                .and_then(|_| json_listing(&text).map_err(|e| format!("bad listing: {}", e)))
+                .and_then(|data| {
+                    // keys come from the terminal now that stdin is used up
+                    input::use_tty().map_err(|e| format!("can't open the terminal: {}", e))?;
+                    Ok(data)
+                });
+            match data {
+                Ok(data) => Source::Stdin(data),
+                Err(e) => {
+                    eprintln!("leightbox: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        None => match (args.input, args.dir) {
+            (Some(path), _) => Source::Input(path, args.delimiter),
+            (None, Some(dir)) => Source::Dir(dir),
+            (None, None) => Source::Sample,
+        },
+    };
+    // --url conflicts with the other sources, so it can only take the sample's place
+    #[cfg(feature = "url")]
+    let source = args.url.map_or(source, Source::Url);
+
+    // a panic leaves raw mode and the alternate screen before the message is printed
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        terminal::restore();
+        hook(info);
+    }));
+
+    let interface = Interface::new(
+        source,
+        args.server,
+        args.wrap,
+        args.bytes,
+        args.ascii,
+        args.dest,
+        config,
+    );
+    let mut interface = match interface {
+        Ok(interface) => interface,
+        Err(e) => {
+            eprintln!("leightbox: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Some(columns) = args.columns {
+        if let Err(e) = interface.set_columns(&columns) {
+            eprintln!("leightbox: {}", e);
+            process::exit(1);
+        }
+    }
+    let result = interface.run();
+
+    // the normal way out shares its cleanup with the panic hook and the signal handler
+    terminal::restore();
+    for line in interface.summary() {
+        println!("{}", line);
+    }
+
+    if let Err(e) = result {
+        eprintln!("leightbox: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an interface listing `files` with their sizes, laid out for an 80x24 terminal whatever
+    // the tests run in
+    fn interface(files: &[(&str, u64)]) -> Interface {
+        let data = files
+            .iter()
+            .map(|(name, size)| {
+                let entry = Entry {
+                    size: Some(*size),
+                    hash: String::new(),
+                    mtime: None,
+                    error: None,
+                };
+                (name.to_string(), entry)
+            })
+            .collect();
+        let dest = PathBuf::from("/nonexistent");
+        let source = Source::Stdin(data);
+        let mut ui =
+            Interface::new(source, None, false, false, true, dest, Config::default()).unwrap();
+        resize(&mut ui, FALLBACK_SIZE);
+        ui
+    }
+
+    fn resize(ui: &mut Interface, size: (u16, u16)) {
+        ui.resize(&mut sink(), size).unwrap();
+    }
+
+    // what the main loop does with keys typed into the list or a prompt
+    fn press(ui: &mut Interface, keys: &[Key]) {
+        let mut out = sink();
+        for key in keys {
+            ui.message = None;
+            if let Mode::Command(_) | Mode::Search(..) | Mode::Filter(_) = ui.mode {
+                ui.prompt_event(&mut out, Event::Key(*key)).unwrap();
+                continue;
+            }
+            if let Key::Char(c @ '0'..='9') = *key {
+                if ui.pending.is_none() && (c != '0' || ui.count.is_some()) {
+                    ui.push_count(&mut out, c).unwrap();
+                    continue;
+                }
+            }
+
+            let (action, arg) = match ui.press(&mut out, *key).unwrap() {
+                Lookup::Prefix => continue,
+                Lookup::Action(action) => (action, None),
+                Lookup::Argument(action, key) => (action, Some(key)),
+                Lookup::None => continue,
+            };
+            let count = ui.take_count(&mut out).unwrap();
+            ui.act(&mut out, action, arg, count).unwrap();
+        }
+    }
+
+    fn typing(ui: &mut Interface, text: &str) {
+        press(ui, &text.chars().map(Key::Char).collect::<Vec<_>>());
+    }
+
+    fn selected(ui: &Interface) -> Vec<String> {
+        let files = ui.selected_files();
+        files.into_iter().map(|(name, _)| name).collect()
+    }
+
+    fn sink() -> RawOut<'static> {
+        Screen::new(Box::new(std::io::sink()))
+    }
+
+    // names of the shown entries, top to bottom
+    fn rows(ui: &Interface) -> Vec<&str> {
+        ui.visible
+            .iter()
+            .map(|e| ui.files[*e].name.as_str())
+            .collect()
+    }
+
+    fn file(name: &str, size: u64, hash: &str) -> FileEntry {
+        let entry = Entry {
+            size: Some(size),
+            hash: hash.to_string(),
+            mtime: None,
+            error: None,
+        };
+        FileEntry::new(name.to_string(), entry)
+    }
+
+    // cells with sizes in plain bytes and the first 8 characters of hashes, leaving out the
+    // `hidden` columns
+    fn cells<'a>(hidden: &'a [SortKey], duplicates: &'a HashMap<String, Vec<String>>) -> Cells<'a> {
+        Cells {
+            units: None,
+            hash_chars: 8,
+            time_format: "%Y-%m-%d",
+            icons: false,
+            unicode: false,
+            hidden,
+            duplicates,
+        }
+    }
+
+    // the rows of `files` with the widths they take on a roomy terminal
+    fn table(files: &[FileEntry], cells: &Cells) -> Vec<String> {
+        let widths = widths(files, cells, false, 200);
+        files.iter().map(|f| row_cells(f, &widths, cells)).collect()
+    }
+
+    fn row(ui: &Interface, name: &str) -> usize {
+        rows(ui).iter().position(|n| *n == name).unwrap()
+    }
+
+    #[test]
+    fn selection_follows_sorting() {
+        let mut ui = interface(&[
+            ("a.txt", 300),
+            ("b.txt", 100),
+            ("c.txt", 200),
+            ("d.txt", 50),
+            ("e.txt", 400),
+        ]);
+        let mut out = sink();
+        for name in ["a.txt", "c.txt", "d.txt"] {
+            let index = row(&ui, name);
+            ui.toggle(&mut out, index).unwrap();
+        }
+        let index = row(&ui, "c.txt");
+        ui.jump_pointer(&mut out, index).unwrap();
+
+        ui.run_command(&mut out, "sort size").unwrap();
+        assert_eq!(rows(&ui), ["d.txt", "b.txt", "c.txt", "a.txt", "e.txt"]);
+        ui.reverse_sort(&mut out).unwrap();
+        assert_eq!(rows(&ui), ["e.txt", "a.txt", "c.txt", "b.txt", "d.txt"]);
+
+        let selected: Vec<bool> = ui.visible.iter().map(|e| ui.files[*e].selected).collect();
+        assert_eq!(selected, [false, true, true, false, true]);
+        // the pointer stays on c.txt, on screen as well
+        assert_eq!(rows(&ui)[ui.index], "c.txt");
+        assert_eq!(ui.pointer.1, ui.lay.list.1 + (ui.index - ui.offset) as u16);
+        assert_eq!(
+            ui.selected_files(),
+            [
+                ("a.txt".to_string(), Some(300)),
+                ("c.txt".to_string(), Some(200)),
+                ("d.txt".to_string(), Some(50)),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_selected_downloads_nothing() {
+        let mut ui = interface(&[("a.txt", 100), ("b.txt", 200)]);
+        let mut out = sink();
+        // a filter that leaves no rows to select
+        ui.run_command(&mut out, "filter zzz").unwrap();
+        assert_eq!(ui.n, 0);
+
+        ui.init_dl(&mut out).unwrap();
+        assert!(ui.download.is_none());
+        assert_eq!(
+            ui.message.as_deref(),
+            Some("Nothing selected — press Space to select files")
+        );
+    }
+
+    #[test]
+    fn sizes_are_right_aligned() {
+        let duplicates = HashMap::new();
+        let hidden = [SortKey::Type, SortKey::Hash, SortKey::Modified];
+        let cells = cells(&hidden, &duplicates);
+        let files = [
+            file("a.bin", 512, ""),
+            file("b.bin", 9876543, ""),
+            file("c.bin", 100, ""),
+        ];
+        let sep = COL_SEPARATOR;
+        assert_eq!(
+            table(&files, &cells),
+            [
+                format!("a.bin{}    512", sep),
+                format!("b.bin{}9876543", sep),
+                format!("c.bin{}    100", sep),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_and_combining_names_line_up() {
+        let duplicates = HashMap::new();
+        let hidden = [SortKey::Type, SortKey::Modified];
+        let cells = cells(&hidden, &duplicates);
+        let hash = "0123456789abcdef";
+        let files = [
+            file("plain.txt", 1, hash),
+            file("日本語のファイル.txt", 22, hash),
+            file("🎉 party.zip", 333, hash),
+            file("cafe\u{301} menu.pdf", 4444, hash),
+        ];
+
+        let rows = table(&files, &cells);
+        // the hash column starts on the same cell of every row, and the rows end together
+        let starts: Vec<usize> = rows
+            .iter()
+            .map(|row| row[..row.find("01234567").unwrap()].width())
+            .collect();
+        let ends: Vec<usize> = rows.iter().map(|row| row.width()).collect();
+        assert!(starts.iter().all(|s| *s == starts[0]), "{:?}", rows);
+        assert!(ends.iter().all(|e| *e == ends[0]), "{:?}", rows);
+    }
+
+    #[test]
+    fn names_are_cut_between_graphemes() {
+        assert_eq!(ellipsize("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}…");
+        assert_eq!(ellipsize("日本語", 4), "日…");
+        assert_eq!(ellipsize("日本語", 5), "日本…");
+        assert_eq!(ellipsize("🎉🎉🎉", 4), "🎉…");
+        // what fits isn't cut at all
+        assert_eq!(ellipsize("日本語", 6), "日本語");
+    }
+
+    #[test]
+    fn narrow_terminals() {
+        for (width, small) in [(1, true), (10, true), (40, false)] {
+            let mut ui = interface(&[("report.pdf", 2048), ("photo.jpg", 4096)]);
+            resize(&mut ui, (width, 24));
+            assert_eq!(ui.lay.small.is_some(), small, "{} columns", width);
+
+            // the notice is drawn instead of the table, and neither may underflow
+            let mut out = sink();
+            ui.write_layout(&mut out).unwrap();
+            if !small {
+                let lay = &ui.lay;
+                assert!(lay.list.0 >= 1 && lay.header.0 >= 1 && lay.footer.0 >= 1);
+                assert!(lay.list.0 as usize + ui.prefix() + ui.w <= width as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn resizing_keeps_the_pointer() {
+        let names: Vec<String> = (0..80).map(|i| format!("file-{:02}.txt", i)).collect();
+        let files: Vec<(&str, u64)> = names.iter().map(|n| (n.as_str(), 1000)).collect();
+        let mut ui = interface(&files);
+        let mut out = sink();
+        ui.jump_pointer(&mut out, 41).unwrap();
+
+        for size in [(120, 40), (60, 14), (80, 24)] {
+            resize(&mut ui, size);
+            assert_eq!(ui.index, 41, "{:?}", size);
+            // scrolled so the row is on the screen
+            assert!((ui.offset..ui.offset + ui.lay.page).contains(&41));
+            assert_eq!(ui.pointer.1, ui.lay.list.1 + (41 - ui.offset) as u16);
+        }
+    }
+
+    #[test]
+    fn download_list_matches_the_rows() {
+        let mut ui = interface(&[
+            ("zeta.iso", 10),
+            ("alpha.txt", 50),
+            ("mid.pdf", 40),
+            ("beta.zip", 20),
+            ("omega.mp4", 30),
+        ]);
+        let mut out = sink();
+        // rows start out in name order
+        assert_eq!(
+            rows(&ui),
+            ["alpha.txt", "beta.zip", "mid.pdf", "omega.mp4", "zeta.iso"]
+        );
+        ui.toggle(&mut out, 3).unwrap();
+        assert_eq!(ui.selected_files(), [("omega.mp4".to_string(), Some(30))]);
+
+        // and the same holds for a row of another sort
+        ui.run_command(&mut out, "clear").unwrap();
+        ui.run_command(&mut out, "sort size").unwrap();
+        ui.toggle(&mut out, 3).unwrap();
+        assert_eq!(rows(&ui)[3], "mid.pdf");
+        assert_eq!(ui.selected_files(), [("mid.pdf".to_string(), Some(40))]);
+    }
+
+    #[test]
+    fn empty_and_single_listings() {
+        let mut ui = interface(&[]);
+        let mut out = sink();
+        assert_eq!((ui.n, ui.w), (0, EMPTY_WIDTH));
+        assert!(ui.lay.small.is_none());
+        ui.write_layout(&mut out).unwrap();
+        // nothing to move to or select
+        ui.move_pointer(&mut out, Direction::Down, 1).unwrap();
+        ui.move_pointer(&mut out, Direction::Up, 1).unwrap();
+        ui.toggle(&mut out, 0).unwrap();
+        ui.toggle_advance(&mut out).unwrap();
+        ui.select_all(&mut out, true).unwrap();
+        ui.invert_selection(&mut out).unwrap();
+        assert_eq!(ui.index, 0);
+        assert!(ui.selected_files().is_empty());
+
+        let mut ui = interface(&[("only.txt", 7)]);
+        ui.write_layout(&mut out).unwrap();
+        ui.move_pointer(&mut out, Direction::Down, 5).unwrap();
+        assert_eq!(ui.index, 0);
+        ui.move_pointer(&mut out, Direction::Up, 5).unwrap();
+        assert_eq!(ui.index, 0);
+        ui.toggle_advance(&mut out).unwrap();
+        assert_eq!(ui.selected_files(), [("only.txt".to_string(), Some(7))]);
+    }
+
+    #[test]
+    fn hashes_of_any_length() {
+        let duplicates = HashMap::new();
+        let pool: Vec<char> = "0123456789abcdef-é日🎉".chars().collect();
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let files: Vec<FileEntry> = (0..5)
+                .map(|i| {
+                    let len = rng.gen_range(0..80);
+                    let hash: String = (0..len)
+                        .map(|_| pool[rng.gen_range(0..pool.len())])
+                        .collect();
+                    file(&format!("{}.bin", i), 1, &hash)
+                })
+                .collect();
+            let mut cells = cells(&[], &duplicates);
+            cells.hash_chars = rng.gen_range(1..=FULL_HASH);
+
+            // whatever the hashes, every row still ends where the table does
+            let rows = table(&files, &cells);
+            let ends: Vec<usize> = rows.iter().map(|row| row.width()).collect();
+            assert!(ends.iter().all(|e| *e == ends[0]), "{:?}", rows);
+        }
+        // a listing without a hash gets a dash
+        assert_eq!(hash_text("", 8), "-");
+        assert_eq!(hash_text("abc", 8), "abc");
+        assert_eq!(hash_text("日本語のハッシュ値", 3), "日本語...");
+    }
+
+    #[test]
+    fn selections_survive_filtering() {
+        let mut ui = interface(&[("a.txt", 1), ("b.pdf", 2), ("c.txt", 4), ("d.pdf", 8)]);
+        let mut out = sink();
+        let selected = |ui: &Interface| -> Vec<String> {
+            ui.selected_files()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+
+        ui.toggle(&mut out, 0).unwrap();
+        ui.run_command(&mut out, "filter pdf").unwrap();
+        assert_eq!(rows(&ui), ["b.pdf", "d.pdf"]);
+        // a.txt is hidden, not forgotten
+        ui.toggle(&mut out, 0).unwrap();
+        assert_eq!(selected(&ui), ["a.txt", "b.pdf"]);
+
+        ui.run_command(&mut out, "filter").unwrap();
+        assert_eq!(rows(&ui), ["a.txt", "b.pdf", "c.txt", "d.pdf"]);
+        let checked: Vec<bool> = ui.files.iter().map(|f| f.selected).collect();
+        assert_eq!(checked, [true, true, false, false]);
+
+        ui.init_dl(&mut out).unwrap();
+        let dl = ui.download.as_ref().unwrap();
+        assert_eq!((dl.files, dl.total), (2, 3));
+        dl.cancel.store(true, Ordering::SeqCst);
+        let queued: Vec<bool> = ui
+            .files
+            .iter()
+            .map(|f| f.status == Status::Queued)
+            .collect();
+        assert_eq!(queued, [true, true, false, false]);
+    }
+
+    #[test]
+    fn table_headers_and_missing_hashes() {
+        // a header may put the columns in any order and leave the hash out
+        let data = table_listing("Size,Name\n10,a.txt\n?,b.txt\n", ',').unwrap();
+        assert_eq!(data["a.txt"].size, Some(10));
+        assert_eq!(data["b.txt"].size, None);
+        assert_eq!(data["a.txt"].hash, "");
+
+        // without a header the columns are name, size and hash, and the hash may be missing
+        let data = table_listing("a.txt;10;abc\nb.txt;20\n", ';').unwrap();
+        assert_eq!(data["a.txt"].hash, "abc");
+        assert_eq!(data["b.txt"].hash, "");
+
+        let err = table_listing("a.txt,10\n\nb.txt,ten\n", ',').unwrap_err();
+        assert_eq!(err, "line 3: size 'ten' isn't a whole number of bytes");
+        let err = table_listing("a.txt,1,h,extra\n", ',').unwrap_err();
+        assert_eq!(err, "line 1: expected at most 3 fields, found 4");
+    }
+
+    #[test]
+    fn select_by_pattern() {
+        let mut ui = interface(&[("a.txt", 1), ("b.txt", 2), ("c.pdf", 4), ("d.pdf", 8)]);
+        let mut out = sink();
+
+        ui.run_command(&mut out, "select *.zip").unwrap();
+        assert_eq!(ui.message.as_deref(), Some("Nothing matches *.zip"));
+        assert!(ui.undo.is_empty());
+
+        ui.run_command(&mut out, "select *.txt").unwrap();
+        assert_eq!(ui.message.as_deref(), Some("Selected 2 entries"));
+        // already selected ones don't count again
+        ui.run_command(&mut out, "select ?.*").unwrap();
+        assert_eq!(ui.message.as_deref(), Some("Selected 2 entries"));
+        ui.run_command(&mut out, "deselect pdf").unwrap();
+        assert_eq!(ui.message.as_deref(), Some("Deselected 2 entries"));
+
+        // entries the filter hides are left as they are
+        ui.run_command(&mut out, "filter *.txt").unwrap();
+        ui.run_command(&mut out, "deselect *").unwrap();
+        assert_eq!(
+            ui.message.as_deref(),
+            Some("Deselected 2 entries, 2 hidden matches left out")
+        );
+        ui.run_command(&mut out, "select pdf").unwrap();
+        assert_eq!(ui.message.as_deref(), Some("Only hidden entries match pdf"));
+        assert!(ui.selected_files().is_empty());
+    }
+
+    #[test]
+    fn names_stay_ascii_without_icons() {
+        let duplicates = HashMap::new();
+        let mut cells = cells(&[], &duplicates);
+        let files = [
+            file("a.tar.gz", 1, "ff"),
+            file(".bashrc", 2, ""),
+            file("movie.mkv", 3, "ee"),
+        ];
+        assert!(table(&files, &cells).iter().all(|row| row.is_ascii()));
+        assert_eq!(kind_text(".bashrc", false), "-");
+
+        cells.icons = true;
+        assert!(table(&files, &cells).iter().all(|row| !row.is_ascii()));
+        let icon = filetype::Kind::Archive.icon();
+        assert_eq!(kind_text("a.tar.gz", true), format!("{} tar.gz", icon));
+    }
+
+    #[test]
+    fn resizing_keeps_the_download() {
+        let mut ui = interface(&[("a.txt", 100), ("b.txt", 200), ("c.txt", 300)]);
+        let mut out = sink();
+        ui.toggle(&mut out, 0).unwrap();
+        ui.toggle(&mut out, 1).unwrap();
+        ui.init_dl(&mut out).unwrap();
+        // the rows are set by hand, the worker's updates are never polled
+        ui.files[0].status = Status::Transferring(50, Some(100));
+        ui.files[1].status = Status::Done;
+
+        for size in [(100, 30), (60, 18), FALLBACK_SIZE] {
+            ui.resize(&mut out, size).unwrap();
+            let lines = out.lines();
+            let line = |name: &str| {
+                let found = lines.iter().find(|line| line.contains(name));
+                found.unwrap().trim_end().to_string()
+            };
+            assert!(line("a.txt").ends_with("50%"), "{:?}", size);
+            assert!(line("b.txt").ends_with("ok"), "{:?}", size);
+            // nothing under the header of the status column for the row left out
+            let status = line("Name").find("Status").unwrap();
+            assert!(line("c.txt").len() < status, "{:?}", size);
+            let footer = line("Downloading");
+            assert!(footer.contains("250 B / 300 B"), "{:?}", size);
+        }
+        assert!(ui.download.is_some());
+        assert_eq!(ui.files[2].status, Status::None);
+
+        if let Some(dl) = &ui.download {
+            dl.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_selections() {
+        let mut ui = interface(&[("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        typing(&mut ui, " ");
+        assert_eq!(selected(&ui), ["a"]);
+        typing(&mut ui, "i");
+        assert_eq!(selected(&ui), ["b", "c", "d"]);
+
+        typing(&mut ui, "u");
+        assert_eq!(selected(&ui), ["a"]);
+        press(&mut ui, &[Key::Ctrl('r')]);
+        assert_eq!(selected(&ui), ["b", "c", "d"]);
+
+        // a count undoes that many steps
+        typing(&mut ui, "2u");
+        assert!(selected(&ui).is_empty());
+        typing(&mut ui, "u");
+        assert_eq!(ui.message.as_deref(), Some("Nothing to undo"));
+
+        // a new change drops what could have been redone
+        typing(&mut ui, "a");
+        assert_eq!(selected(&ui), ["a", "b", "c", "d"]);
+        press(&mut ui, &[Key::Ctrl('r')]);
+        assert_eq!(ui.message.as_deref(), Some("Nothing to redo"));
+        // selecting what's already selected isn't a step of its own
+        typing(&mut ui, ":select *\nu");
+        assert!(selected(&ui).is_empty());
+    }
+
+    #[test]
+    fn filtering() {
+        let mut ui = interface(&[("a.pdf", 1), ("b.txt", 2), ("c.pdf", 3), ("d.txt", 4)]);
+        typing(&mut ui, "fpdf\n");
+        assert_eq!(ui.filter.as_deref(), Some("pdf"));
+        assert_eq!(rows(&ui), ["a.pdf", "c.pdf"]);
+
+        // selecting everything only takes what's shown
+        typing(&mut ui, "a");
+        assert_eq!(selected(&ui), ["a.pdf", "c.pdf"]);
+
+        // the prompt starts from the filter in place, and cancelling it shows everything
+        typing(&mut ui, "f");
+        assert_eq!(ui.mode, Mode::Filter("pdf".to_string()));
+        press(&mut ui, &[Key::Backspace, Key::Backspace, Key::Backspace]);
+        typing(&mut ui, "txt");
+        assert_eq!(rows(&ui), ["b.txt", "d.txt"]);
+        press(&mut ui, &[Key::Esc]);
+        assert_eq!(ui.filter, None);
+        assert_eq!(ui.n, 4);
+
+        typing(&mut ui, ":filter zzz\n");
+        assert_eq!(ui.n, 0);
+        typing(&mut ui, ":filter\n");
+        assert_eq!(ui.n, 4);
+        assert_eq!(selected(&ui), ["a.pdf", "c.pdf"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_one_copy() {
+        let mut ui = interface(&[
+            ("a.iso", 1),
+            ("b.iso", 1),
+            ("c.iso", 1),
+            ("d.txt", 2),
+            ("e.txt", 2),
+            ("f.txt", 3),
+        ]);
+        let hashes = ["ff", "ff", "ff", "ee", "ee", "dd"];
+        for (file, hash) in ui.files.iter_mut().zip(hashes) {
+            file.hash = hash.to_string();
+        }
+        ui.duplicates = duplicate_groups(&ui.files);
+
+        // the copy that stays is the first by name, whichever way the list is sorted
+        typing(&mut ui, "Sa:dedupe\n");
+        assert_eq!(selected(&ui), ["a.iso", "d.txt", "f.txt"]);
+        assert_eq!(ui.message.as_deref(), Some("Deselected 3 duplicates"));
+
+        typing(&mut ui, ":dedupe\n");
+        assert_eq!(ui.message.as_deref(), Some("No selected duplicates"));
+
+        // unselected copies are left alone
+        typing(&mut ui, "u");
+        assert_eq!(selected(&ui).len(), 6);
+        typing(&mut ui, ":clear\n:select [bc]*\n:dedupe\n");
+        assert_eq!(selected(&ui), ["b.iso"]);
+        assert_eq!(ui.message.as_deref(), Some("Deselected 1 duplicate"));
+    }
+
+    #[test]
+    fn visual_ranges() {
+        let mut ui = interface(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5)]);
+        typing(&mut ui, "jv2j");
+        assert_eq!(ui.mode, Mode::Visual(1));
+        assert_eq!(ui.visual_range(), Some(1..=3));
+        typing(&mut ui, " ");
+        assert_eq!(ui.mode, Mode::Normal);
+        assert_eq!(selected(&ui), ["b", "c", "d"]);
+
+        // a range marked upwards toggles the same way, and Esc drops it
+        typing(&mut ui, "vkk");
+        assert_eq!(ui.visual_range(), Some(1..=3));
+        press(&mut ui, &[Key::Esc]);
+        assert_eq!(selected(&ui), ["b", "c", "d"]);
+        typing(&mut ui, "Gvggv");
+        assert_eq!(selected(&ui), ["a", "e"]);
+
+        // and the whole range is one undo step
+        typing(&mut ui, "u");
+        assert_eq!(selected(&ui), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn marks() {
+        let mut ui = interface(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5)]);
+        typing(&mut ui, "3jmaG'a");
+        assert_eq!(ui.index, 3);
+
+        // the mark stays with its entry when the order changes
+        typing(&mut ui, "S`a");
+        assert_eq!(rows(&ui)[ui.index], "d");
+
+        typing(&mut ui, "'b");
+        assert_eq!(ui.message.as_deref(), Some("Mark 'b' isn't set"));
+        typing(&mut ui, "m1");
+        assert_eq!(ui.message.as_deref(), Some("Not a mark letter: 1"));
+        typing(&mut ui, ":filter a\n'a");
+        assert_eq!(
+            ui.message.as_deref(),
+            Some("Mark 'a' is hidden by the filter")
+        );
+    }
+
+    #[test]
+    fn counts() {
+        let names: Vec<String> = (0..10).map(|i| format!("{:02}", i)).collect();
+        let files: Vec<_> = names.iter().map(|n| (n.as_str(), 1)).collect();
+        let mut ui = interface(&files);
+        typing(&mut ui, "3j");
+        assert_eq!(ui.index, 3);
+        typing(&mut ui, "2k");
+        assert_eq!(ui.index, 1);
+        typing(&mut ui, "5G");
+        assert_eq!(ui.index, 4);
+        typing(&mut ui, "2gg");
+        assert_eq!(ui.index, 1);
+        typing(&mut ui, "7\n");
+        assert_eq!(ui.index, 6);
+        // past the end stops at the last entry
+        typing(&mut ui, "25j");
+        assert_eq!(ui.index, 9);
+        typing(&mut ui, "99G");
+        assert_eq!(ui.index, 9);
+
+        // Esc drops a count without doing anything else
+        typing(&mut ui, "gg5");
+        press(&mut ui, &[Key::Esc]);
+        typing(&mut ui, "j");
+        assert_eq!(ui.index, 1);
+        // and a count before Space toggles just the one row
+        typing(&mut ui, "3 ");
+        assert_eq!(selected(&ui), ["01"]);
+    }
+
+    #[test]
+    fn searching() {
+        let mut ui = interface(&[
+            ("alpha", 1),
+            ("beta", 2),
+            ("delta", 3),
+            ("gamma", 4),
+            ("zeta", 5),
+        ]);
+        // the pointer follows the first match as the query is typed, whatever its case
+        typing(&mut ui, "/Ta");
+        assert_eq!(ui.index, 1);
+        typing(&mut ui, "\n");
+        assert_eq!(ui.message.as_deref(), Some("match 1/3 for Ta"));
+
+        typing(&mut ui, "n");
+        assert_eq!(rows(&ui)[ui.index], "delta");
+        assert_eq!(ui.message.as_deref(), Some("match 2/3 for Ta"));
+        typing(&mut ui, "nn");
+        assert_eq!(rows(&ui)[ui.index], "beta");
+        typing(&mut ui, "N");
+        assert_eq!(rows(&ui)[ui.index], "zeta");
+
+        // cancelling goes back to where the search started
+        typing(&mut ui, "/gam");
+        assert_eq!(rows(&ui)[ui.index], "gamma");
+        press(&mut ui, &[Key::Esc]);
+        assert_eq!(rows(&ui)[ui.index], "zeta");
+
+        typing(&mut ui, "/xyz\nn");
+        assert_eq!(ui.message.as_deref(), Some("No match for xyz"));
+    }
+
+    #[test]
+    fn scrolling() {
+        let names: Vec<String> = (0..100).map(|i| format!("{:03}", i)).collect();
+        let files: Vec<_> = names.iter().map(|n| (n.as_str(), 1)).collect();
+        let mut ui = interface(&files);
+        let page = ui.lay.page;
+        let margin = ui.scrolloff;
+        assert!(page > 2 * margin + 1);
+
+        // the pointer keeps `scrolloff` rows below it before the list scrolls
+        typing(&mut ui, &format!("{}j", page - margin - 1));
+        assert_eq!(ui.offset, 0);
+        typing(&mut ui, "j");
+        assert_eq!(ui.offset, 1);
+        let index = ui.index;
+
+        typing(&mut ui, "zt");
+        assert_eq!(ui.offset, index - margin);
+        typing(&mut ui, "zz");
+        assert_eq!(ui.offset, index - (page - 1) / 2);
+        typing(&mut ui, "zb");
+        assert_eq!(ui.offset, index + 1 + margin - page);
+        // none of them move the pointer
+        assert_eq!(ui.index, index);
+
+        // and above it, unless the list can't go any further
+        typing(&mut ui, "G");
+        assert_eq!(ui.offset, 100 - page);
+        typing(&mut ui, "zt");
+        assert_eq!(ui.offset, 100 - page);
+        typing(&mut ui, &format!("{}k", page - margin - 1));
+        assert_eq!(ui.offset, 100 - page);
+        typing(&mut ui, "k");
+        assert_eq!(ui.offset, 100 - page - 1);
+
+        typing(&mut ui, "ggzz");
+        assert_eq!(ui.offset, 0);
+    }
+
+    #[test]
+    fn columns_command() {
+        let mut ui = interface(&[("a.txt", 1), ("b.txt", 2)]);
+        typing(&mut ui, ":columns name, size\n");
+        assert_eq!(ui.hidden, [SortKey::Type, SortKey::Hash, SortKey::Modified]);
+
+        typing(&mut ui, ":columns name,owner\n");
+        assert_eq!(
+            ui.message.as_deref(),
+            Some("'owner' isn't a column, try name, type, size, hash or modified")
+        );
+        // a bad name leaves the columns as they were
+        assert_eq!(ui.hidden.len(), 3);
+
+        typing(&mut ui, ":columns\n");
+        assert!(ui.hidden.is_empty());
+
+        // a hidden hash column doesn't count as something to show
+        ui.hash_chars = 0;
+        typing(&mut ui, ":columns hash\n");
+        assert_eq!(ui.message.as_deref(), Some("Can't hide every column"));
+        assert!(ui.hidden.is_empty());
+    }
+
+    #[test]
+    fn boxes_in_ascii() {
+        let mut ui = interface(&[("a.txt", 1)]);
+        let mut out = sink();
+        let lines = ["one".to_string(), "two".to_string()];
+        ui.write_box(&mut out, "Title", &lines).unwrap();
+        let screen = out.lines();
+        let rows: Vec<&str> = screen
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "+-------+",
+                "| Title |",
+                "| one   |",
+                "| two   |",
+                "|       |",
+                "+-------+",
+            ]
+        );
+
+        // and the help and details drawn with it
+        let edges = |out: &mut RawOut| {
+            let screen = out.lines();
+            let mut rows = screen.iter().map(|l| l.trim()).filter(|l| !l.is_empty());
+            rows.all(|l| {
+                (l.starts_with("+-") && l.ends_with('+'))
+                    || (l.starts_with("| ") && l.ends_with('|'))
+            })
+        };
+        ui.clear(&mut out).unwrap();
+        ui.write_help(&mut out).unwrap();
+        assert!(edges(&mut out));
+        ui.clear(&mut out).unwrap();
+        ui.write_detail(&mut out).unwrap();
+        assert!(edges(&mut out));
+
+        ui.unicode = true;
+        ui.write_box(&mut out, "Title", &lines).unwrap();
+        assert!(out.lines().iter().any(|line| line.contains("┌───────┐")));
+    }
 }
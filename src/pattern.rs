@@ -0,0 +1,209 @@
+/// Whether `query` should be read as a glob rather than plain text.
+pub fn is_glob(query: &str) -> bool {
+    query.contains(['*', '?', '['])
+}
+
+/// Matches a filter query, as a glob if it has wildcards and fuzzily otherwise.
+pub fn filter_match(query: &str, name: &str) -> bool {
+    match is_glob(query) {
+        true => glob_match(query, name),
+        false => fuzzy_match(query, name),
+    }
+}
+
+/// Matches a `:select` pattern, as a glob if it has wildcards and a substring otherwise.
+pub fn select_match(pattern: &str, name: &str) -> bool {
+    match is_glob(pattern) {
+        true => glob_match(pattern, name),
+        false => name.contains(pattern),
+    }
+}
+
+/// Characters of `name` that a filter query picked out, none for globs since they cover it all.
+pub fn filter_positions(query: &str, name: &str) -> Vec<usize> {
+    match is_glob(query) {
+        true => Vec::new(),
+        false => fuzzy_positions(query, name).unwrap_or_default(),
+    }
+}
+
+/// Characters of the first place `query` appears in `name`, ignoring case like searching does.
+pub fn search_positions(query: &str, name: &str) -> Vec<usize> {
+    let lower = |c: char| c.to_lowercase().collect::<String>();
+    let query: Vec<String> = query.chars().map(lower).collect();
+    let name: Vec<String> = name.chars().map(lower).collect();
+    if query.is_empty() || query.len() > name.len() {
+        return Vec::new();
+    }
+
+    (0..=name.len() - query.len())
+        .find(|start| name[*start..*start + query.len()] == query[..])
+        .map_or(Vec::new(), |start| (start..start + query.len()).collect())
+}
+
+/// Where each query character was found by `fuzzy_match`, taking the earliest one each time.
+fn fuzzy_positions(query: &str, name: &str) -> Option<Vec<usize>> {
+    let mut chars = name
+        .chars()
+        .enumerate()
+        .flat_map(|(i, c)| c.to_lowercase().map(move |c| (i, c)));
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|q| chars.find(|(_, c)| *c == q).map(|(i, _)| i))
+        .collect()
+}
+
+pub fn fuzzy_match(query: &str, name: &str) -> bool {
+    // every query character has to appear in order, ignoring case
+    let mut chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| chars.any(|c| c == q))
+}
+
+/// Shell-style glob over the whole name with `*`, `?` and `[...]` classes.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // where the last `*` was and how much of the name it has swallowed
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = class_match(&pattern[p..], name[n]) {
+                    if matched {
+                        p += len;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    // an unclosed bracket is just a bracket
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // a mismatch lets the last `*` take one more character
+        match star {
+            Some((sp, sn)) => {
+                star = Some((sp, sn + 1));
+                p = sp + 1;
+                n = sn + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether `c` is in the class at the start of `pattern`, and how long the class is.
+fn class_match(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = *pattern.get(i)?;
+        // a `]` right after the opening bracket is part of the class
+        if lo == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(hi)) if *hi != ']' => {
+                matched |= (lo..=*hi).contains(&c);
+                i += 3;
+            }
+            _ => {
+                matched |= lo == c;
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(!glob_match("*.txt", "notes.txt.gz"));
+        assert!(glob_match("?.pdf", "a.pdf"));
+        assert!(!glob_match("?.pdf", "ab.pdf"));
+        assert!(!glob_match("?", ""));
+        // the name has to match as a whole, not just a part of it
+        assert!(!glob_match("note", "notes"));
+    }
+
+    #[test]
+    fn stars_backtrack() {
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a*b*c", "axxbyybzzc"));
+        assert!(glob_match("a*b*c", "abcbc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(glob_match("*a*a*a", "aaaa"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(glob_match("[a-c].txt", "b.txt"));
+        assert!(!glob_match("[a-c].txt", "d.txt"));
+        assert!(glob_match("[!a-c].txt", "d.txt"));
+        assert!(!glob_match("[!a-c].txt", "a.txt"));
+        assert!(glob_match("[^a-c].txt", "z.txt"));
+        assert!(glob_match("[xyz]", "y"));
+        // a `]` right after the bracket is one of the characters
+        assert!(glob_match("[]a]", "]"));
+        assert!(glob_match("[!]]", "a"));
+        assert!(!glob_match("[!]]", "]"));
+        // a `-` at the end is just a dash
+        assert!(glob_match("[a-]", "-"));
+        // an unclosed `[` is an ordinary character
+        assert!(glob_match("[ab", "[ab"));
+        assert!(!glob_match("[ab", "a"));
+    }
+
+    #[test]
+    fn class_lengths() {
+        assert_eq!(class_match(&['[', 'a', ']'], 'a'), Some((true, 3)));
+        assert_eq!(
+            class_match(&['[', '!', 'a', '-', 'c', ']'], 'b'),
+            Some((false, 6))
+        );
+        assert_eq!(class_match(&['[', ']', ']'], ']'), Some((true, 3)));
+        assert_eq!(class_match(&['[', 'a', 'b'], 'a'), None);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    io::{self, Write},
+    mem,
+    ops::Range,
+    rc::Rc,
+};
+use termion::{clear, cursor, style, terminal_size};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    // a grapheme, empty for the right half of a wide one
+    text: String,
+    // escapes in effect since the last reset
+    style: Rc<str>,
+}
+
+impl Cell {
+    fn blank(style: &Rc<str>) -> Self {
+        Self {
+            text: " ".to_string(),
+            style: style.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    fn new((width, height): (usize, usize)) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::blank(&Rc::from("")); width * height],
+        }
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn resized(&self, (width, height): (usize, usize)) -> Self {
+        let mut grid = Grid::new((width, height));
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                grid.cells[y * width + x] = self.get(x, y).clone();
+            }
+            // a wide character cut in half at the new edge is gone
+            if width > 0 && width < self.width && self.get(width, y).text.is_empty() {
+                grid.cells[y * width + width - 1] = Cell::blank(&self.get(width, y).style);
+            }
+        }
+
+        grid
+    }
+
+    fn get(&self, x: usize, y: usize) -> &Cell {
+        &self.cells[y * self.width + x]
+    }
+
+    fn clear(&mut self, y: usize, xs: Range<usize>, style: &Rc<str>) {
+        if y >= self.height {
+            return;
+        }
+        let row = y * self.width;
+        for x in xs.start..xs.end.min(self.width) {
+            self.cells[row + x] = Cell::blank(style);
+        }
+    }
+
+    /// Puts `text` at column `x` of line `y`, a wide one takes the next column too.
+    fn put(&mut self, x: usize, y: usize, text: &str, width: usize, style: &Rc<str>) {
+        if y >= self.height || x + width > self.width {
+            return;
+        }
+
+        // wide characters that get half overwritten leave a blank behind
+        for i in x..x + width {
+            self.split(i, y);
+        }
+        let row = y * self.width;
+        self.cells[row + x] = Cell {
+            text: text.to_string(),
+            style: style.clone(),
+        };
+        if width == 2 {
+            self.cells[row + x + 1] = Cell {
+                text: String::new(),
+                style: style.clone(),
+            };
+        }
+    }
+
+    fn split(&mut self, x: usize, y: usize) {
+        let row = y * self.width;
+        if self.cells[row + x].text.is_empty() && x > 0 {
+            let style = self.cells[row + x - 1].style.clone();
+            self.cells[row + x - 1] = Cell::blank(&style);
+        }
+        if x + 1 < self.width && self.cells[row + x + 1].text.is_empty() {
+            let style = self.cells[row + x + 1].style.clone();
+            self.cells[row + x + 1] = Cell::blank(&style);
+        }
+    }
+}
+
+/// Output that keeps what's on the terminal and only sends the cells that changed on `flush`.
+///
+/// Written text and escapes are collected and applied to a copy of the screen when flushing,
+/// since `write!` hands them over in pieces. Escapes that don't draw, like hiding the cursor,
+/// go out as they are.
+pub struct Screen<W: Write> {
+    out: W,
+    pending: Vec<u8>,
+    // what the next flush should leave on the terminal, and what's on it now if that's known
+    want: Grid,
+    shown: Option<Grid>,
+    // where the written text is going and in which style
+    cursor: (usize, usize),
+    style: Rc<str>,
+    // where the terminal's cursor was left after the last flush
+    placed: (usize, usize),
+    passthrough: Vec<u8>,
+}
+
+impl<W: Write> Screen<W> {
+    pub fn new(out: W) -> Self {
+        Self::sized(out, size())
+    }
+
+    fn sized(out: W, size: (usize, usize)) -> Self {
+        Self {
+            out,
+            pending: Vec::new(),
+            want: Grid::new(size),
+            shown: None,
+            cursor: (0, 0),
+            style: Rc::from(""),
+            placed: (0, 0),
+            passthrough: Vec::new(),
+        }
+    }
+
+    /// Takes the terminal's new size, what's written before it is cut off at the new edges.
+    pub fn resize(&mut self, (width, height): (u16, u16)) {
+        self.parse();
+        self.want = self.want.resized((width as usize, height as usize));
+    }
+
+    /// Forgets what's on the terminal so the next flush repaints all of it, for when something
+    /// else has drawn there.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// The text of each line as the next flush would leave it.
+    #[cfg(test)]
+    pub fn lines(&mut self) -> Vec<String> {
+        self.parse();
+        let grid = &self.want;
+        (0..grid.height)
+            .map(|y| {
+                (0..grid.width)
+                    .map(|x| grid.get(x, y).text.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn parse(&mut self) {
+        let pending = mem::take(&mut self.pending);
+        let text = String::from_utf8_lossy(&pending);
+        let mut rest: &str = &text;
+
+        while !rest.is_empty() {
+            if let Some(body) = rest.strip_prefix("\x1B[") {
+                // parameters up to the final byte
+                let end = body
+                    .find(|c: char| ('@'..='~').contains(&c))
+                    .map_or(body.len(), |i| i + 1);
+                self.control(&rest[..end + 2], &body[..end]);
+                rest = &body[end..];
+            } else if rest.starts_with("\x1B]") {
+                // operating system commands run up to the bell
+                let end = rest.find('\x07').map_or(rest.len(), |i| i + 1);
+                self.passthrough.extend(&rest.as_bytes()[..end]);
+                rest = &rest[end..];
+            } else if let Some(c) = rest.chars().next().filter(|c| c.is_control()) {
+                match c {
+                    '\r' => self.cursor.0 = 0,
+                    '\n' => self.cursor.1 += 1,
+                    c => self.passthrough.extend(c.to_string().as_bytes()),
+                }
+                rest = &rest[c.len_utf8()..];
+            } else {
+                let end = rest.find(|c: char| c.is_control()).unwrap_or(rest.len());
+                self.print(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    fn control(&mut self, escape: &str, body: &str) {
+        let (params, last) = body.split_at(body.len().saturating_sub(1));
+        let (x, y) = self.cursor;
+
+        match (last, params) {
+            ("H", _) => {
+                let mut pos = params.split(';').map(|p| p.parse::<usize>().unwrap_or(1));
+                let row = pos.next().unwrap_or(1);
+                let col = pos.next().unwrap_or(1);
+                self.cursor = (col.saturating_sub(1), row.saturating_sub(1));
+            }
+            ("m", "" | "0") => self.style = Rc::from(""),
+            ("m", _) => self.style = Rc::from(format!("{}{}", self.style, escape)),
+            ("K", "" | "0") => self.want.clear(y, x..usize::MAX, &self.style),
+            ("K", "1") => self.want.clear(y, 0..x + 1, &self.style),
+            ("K", "2") => self.want.clear(y, 0..usize::MAX, &self.style),
+            ("J", "2") => self.want = Grid::new(self.want.size()),
+            _ => self.passthrough.extend(escape.as_bytes()),
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        for g in text.graphemes(true) {
+            let width = g.width();
+            if width == 0 {
+                continue;
+            }
+            let (x, y) = self.cursor;
+            self.want.put(x, y, g, width, &self.style);
+            self.cursor.0 += width;
+        }
+    }
+
+    /// Everything that has to be sent to make the terminal look like `want`.
+    fn diff(&mut self) -> Vec<u8> {
+        let mut out = mem::take(&mut self.passthrough);
+        let shown = match self.shown.take() {
+            Some(shown) if shown.size() == self.want.size() => shown,
+            // the alternate screen keeps its text through a resize, cut off at the new size
+            Some(shown) => shown.resized(self.want.size()),
+            None => {
+                out.extend(format!("{}{}", style::Reset, clear::All).as_bytes());
+                Grid::new(self.want.size())
+            }
+        };
+
+        // the terminal's style at the start is unknown, the reset makes it empty
+        let mut style: Option<Rc<str>> = None;
+        let (width, height) = self.want.size();
+        for y in 0..height {
+            // where the terminal's cursor is while it's on this line
+            let mut at = None;
+            let mut x = 0;
+            while x < width {
+                let cell = self.want.get(x, y);
+                if cell == shown.get(x, y) || cell.text.is_empty() {
+                    x += 1;
+                    continue;
+                }
+
+                if at != Some(x) {
+                    out.extend(
+                        cursor::Goto(x as u16 + 1, y as u16 + 1)
+                            .to_string()
+                            .as_bytes(),
+                    );
+                }
+                if style.as_ref() != Some(&cell.style) {
+                    out.extend(format!("{}{}", style::Reset, cell.style).as_bytes());
+                    style = Some(cell.style.clone());
+                }
+                out.extend(cell.text.as_bytes());
+
+                x += cell.text.width().max(1);
+                at = Some(x);
+            }
+        }
+
+        if style.is_some() {
+            out.extend(style::Reset.to_string().as_bytes());
+        }
+        // drawing moves the cursor, it goes back to where the last escape put it
+        if !out.is_empty() || self.cursor != self.placed {
+            let (x, y) = self.cursor;
+            out.extend(
+                cursor::Goto(x as u16 + 1, y as u16 + 1)
+                    .to_string()
+                    .as_bytes(),
+            );
+            self.placed = self.cursor;
+        }
+
+        let mut shown = shown;
+        shown.clone_from(&self.want);
+        self.shown = Some(shown);
+
+        out
+    }
+}
+
+impl<W: Write> Write for Screen<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.parse();
+        let out = self.diff();
+        if !out.is_empty() {
+            self.out.write_all(&out)?;
+        }
+        self.out.flush()
+    }
+}
+
+fn size() -> (usize, usize) {
+    terminal_size().map_or((80, 24), |(w, h)| (w as usize, h as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // a row of the list, in reverse video under the pointer
+    fn row(y: usize, pointer: bool) -> String {
+        let style = match pointer {
+            true => style::Invert.to_string(),
+            false => String::new(),
+        };
+        format!(
+            "{}{}[ ] file-{:03}.tar.gz        {:>9} B{}",
+            cursor::Goto(3, y as u16 + 1),
+            style,
+            y,
+            y * 1024,
+            style::Reset
+        )
+    }
+
+    // the 500 rows of a frame with the pointer on row `pointer`
+    fn frame(screen: &mut Screen<Vec<u8>>, pointer: usize) {
+        for y in 0..500 {
+            write!(screen, "{}", row(y, y == pointer)).unwrap();
+        }
+        screen.flush().unwrap();
+    }
+
+    #[test]
+    fn moving_the_pointer_sends_two_rows() {
+        let mut screen = Screen::sized(Vec::new(), (100, 500));
+        frame(&mut screen, 0);
+        let full = screen.out.len();
+
+        for i in 1..100 {
+            // everything is written again, only the two rows that changed should go out
+            screen.out.clear();
+            frame(&mut screen, i);
+            assert!(screen.out.len() < 2 * full / 500 + 64, "frame {}", i);
+        }
+    }
+
+    #[test]
+    #[ignore = "timing, run with --ignored on a release build"]
+    fn redrawing_500_rows_is_fast() {
+        let mut screen = Screen::sized(Vec::new(), (100, 500));
+        frame(&mut screen, 0);
+
+        let frames = 100;
+        let started = Instant::now();
+        for i in 1..frames {
+            frame(&mut screen, i);
+        }
+        let took = started.elapsed() / (frames - 1) as u32;
+        assert!(took < Duration::from_millis(20), "{:?} per frame", took);
+    }
+
+    #[test]
+    fn clearing_keeps_the_size() {
+        let mut screen = Screen::sized(Vec::new(), (10, 3));
+        write!(screen, "{}{}", clear::All, cursor::Goto(1, 3)).unwrap();
+        screen.flush().unwrap();
+        assert_eq!(screen.want.size(), (10, 3));
+
+        // until a resize, which cuts off what doesn't fit
+        write!(screen, "{}0123456789", cursor::Goto(1, 1)).unwrap();
+        screen.resize((4, 2));
+        assert_eq!(screen.want.size(), (4, 2));
+        assert_eq!(screen.want.get(3, 0).text, "3");
+        write!(screen, "{}", clear::All).unwrap();
+        screen.flush().unwrap();
+        assert_eq!(screen.want.size(), (4, 2));
+    }
+}
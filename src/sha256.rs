@@ -0,0 +1,192 @@
+use std::{
+    cmp::min,
+    io::{self, Read},
+};
+
+// first 32 bits of the fractional parts of the cube roots of the first 64 primes
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+// first 32 bits of the fractional parts of the square roots of the first 8 primes
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+// bytes read from a file at a time
+const CHUNK: usize = 64 * 1024;
+
+/// The SHA-256 digest of everything `reader` has, in lowercase hex.
+pub fn hex_digest(mut reader: impl Read) -> io::Result<String> {
+    let mut state = H;
+    let mut buf = vec![0u8; CHUNK];
+    // bytes of an unfinished block carried over to the next read
+    let mut block = [0u8; 64];
+    let (mut filled, mut len) = (0, 0u64);
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        len += n as u64;
+
+        // whole blocks are hashed straight from the buffer, only what's left over is copied
+        let mut data = &buf[..n];
+        if filled > 0 {
+            let take = min(64 - filled, data.len());
+            block[filled..filled + take].copy_from_slice(&data[..take]);
+            (filled, data) = (filled + take, &data[take..]);
+            if filled < 64 {
+                continue;
+            }
+            compress(&mut state, &block);
+        }
+        let mut blocks = data.chunks_exact(64);
+        for chunk in &mut blocks {
+            compress(&mut state, chunk.try_into().expect("chunks are 64 bytes"));
+        }
+        let rest = blocks.remainder();
+        block[..rest.len()].copy_from_slice(rest);
+        filled = rest.len();
+    }
+
+    // a one bit, zeros up to the last eight bytes of a block and the length in bits
+    block[filled] = 0x80;
+    block[filled + 1..].fill(0);
+    if filled >= 56 {
+        compress(&mut state, &block);
+        block = [0; 64];
+    }
+    block[56..].copy_from_slice(&(len * 8).to_be_bytes());
+    compress(&mut state, &block);
+
+    Ok(state.iter().map(|word| format!("{:08x}", word)).collect())
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        (h, g, f, e) = (g, f, e, d.wrapping_add(t1));
+        (d, c, b, a) = (c, b, a, t1.wrapping_add(t2));
+    }
+
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hands out at most `step` bytes per read, like a slow pipe
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = min(min(self.step, buf.len()), self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn digest(data: &[u8]) -> String {
+        hex_digest(data).unwrap()
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(
+            digest(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // 448 bits, so the padding takes a second block
+        assert_eq!(
+            digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn lengths_around_a_block() {
+        // the length still fits in the last block up to 55 bytes, not from 56 on
+        let cases = [
+            (
+                55,
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                56,
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                63,
+                "7d3e74a05d7db15bce4ad9ec0658ea98e3f06eeecf16b4c6fff2da457ddc2f34",
+            ),
+            (
+                64,
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+            (
+                65,
+                "635361c48bb9eab14198e76ea8ab7f1a41685d6ad62aa9146d301d4f17eb0ae0",
+            ),
+        ];
+        for (len, hex) in cases {
+            assert_eq!(digest(&vec![b'a'; len]), hex, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn short_reads() {
+        // more than a chunk, in reads that split blocks at every offset
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let hex = "e24bc62381f1224fbbb74688663f8f9743b9680b193edd666835e97b06e730eb";
+        assert!(data.len() > CHUNK);
+        assert_eq!(digest(&data), hex);
+        for step in [1, 7, 63, 65, 1000] {
+            let reader = Trickle { data: &data, step };
+            assert_eq!(hex_digest(reader).unwrap(), hex, "{} bytes per read", step);
+        }
+    }
+}
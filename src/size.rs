@@ -0,0 +1,51 @@
+use serde::Deserialize;
+
+/// Unit style for readable file sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    // powers of 1024, `KiB`
+    #[default]
+    Binary,
+    // powers of 1000, `kB`
+    Si,
+}
+
+impl Units {
+    fn step(self) -> f64 {
+        match self {
+            Units::Binary => 1024.0,
+            Units::Si => 1000.0,
+        }
+    }
+
+    fn names(self) -> [&'static str; 6] {
+        match self {
+            Units::Binary => ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"],
+            Units::Si => ["kB", "MB", "GB", "TB", "PB", "EB"],
+        }
+    }
+}
+
+/// `size` as shown in the list, in exact bytes when there are no `units`.
+pub fn format(size: u64, units: Option<Units>) -> String {
+    let units = match units {
+        Some(units) => units,
+        None => return size.to_string(),
+    };
+
+    let step = units.step();
+    if (size as f64) < step {
+        return format!("{} B", size);
+    }
+
+    // the largest unit that keeps the number at least 1
+    let mut value = size as f64 / step;
+    let mut unit = 0;
+    while value >= step && unit < units.names().len() - 1 {
+        value /= step;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, units.names()[unit])
+}
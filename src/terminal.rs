@@ -0,0 +1,174 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use termion::{
+    cursor,
+    screen::{ToAlternateScreen, ToMainScreen},
+    terminal_size,
+};
+
+// the reporting modes of termion's MouseTerminal, and motion without a button for hovering
+const ENTER_MOUSE: &str = "\x1B[?1000h\x1B[?1002h\x1B[?1003h\x1B[?1015h\x1B[?1006h";
+const EXIT_MOUSE: &str = "\x1B[?1006l\x1B[?1015l\x1B[?1003l\x1B[?1002l\x1B[?1000l";
+
+// settings from before raw mode, and whether they still have to be put back
+static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+static ACTIVE: Active = Active::new();
+
+/// Whether the terminal is taken over, kept so that only one caller gives it back.
+struct Active {
+    on: AtomicBool,
+    // whether enter switched to the alternate screen, so restore knows to switch back
+    alternate: AtomicBool,
+}
+
+impl Active {
+    const fn new() -> Self {
+        Self {
+            on: AtomicBool::new(false),
+            alternate: AtomicBool::new(true),
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.on.load(Ordering::SeqCst)
+    }
+
+    fn start(&self, alternate: bool) {
+        self.alternate.store(alternate, Ordering::SeqCst);
+        self.on.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the alternate screen was used, for the first caller after `start` only.
+    fn finish(&self) -> Option<bool> {
+        self.on
+            .swap(false, Ordering::SeqCst)
+            .then(|| self.alternate.load(Ordering::SeqCst))
+    }
+}
+
+/// Switches to raw mode, with mouse reporting if `mouse` is set.
+///
+/// Without `alternate` the interface is drawn on the main screen, over lines that are scrolled
+/// into the scrollback first.
+pub fn enter(mouse: bool, alternate: bool) -> io::Result<()> {
+    // a stray SIGCONT must not save the raw settings as the ones to restore
+    if ACTIVE.is_on() {
+        return Ok(());
+    }
+
+    // SAFETY: termios is plain data and tcgetattr only writes into it
+    let mut ios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut ios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    *ORIGINAL.lock().unwrap() = Some(ios);
+
+    let mut raw = ios;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    if unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSADRAIN, &raw) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    ACTIVE.start(alternate);
+    let mouse = match mouse {
+        true => ENTER_MOUSE,
+        false => "",
+    };
+    let screen = match alternate {
+        true => ToAlternateScreen.to_string(),
+        // raw mode doesn't add a carriage return, so these only scroll once at the bottom
+        false => "\n".repeat(rows() as usize),
+    };
+    write_all(&format!("{}{}", screen, mouse));
+
+    Ok(())
+}
+
+/// Puts the terminal back the way `enter` found it, returning whether there was anything to undo.
+///
+/// Shared by the normal exit path, the panic hook and the signal thread, so it may run more
+/// than once and from any thread. It writes straight to the file descriptor because the main
+/// thread could be holding the stdout lock.
+pub fn restore() -> bool {
+    let alternate = match ACTIVE.finish() {
+        Some(alternate) => alternate,
+        None => return false,
+    };
+    write_all(&exit_sequence(alternate, rows()));
+
+    // a poisoned lock still holds the saved settings
+    let original = match ORIGINAL.lock() {
+        Ok(ios) => *ios,
+        Err(e) => *e.into_inner(),
+    };
+    if let Some(ios) = original {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSADRAIN, &ios) };
+    }
+
+    true
+}
+
+/// What turns mouse reporting off and leaves the screen `enter` switched to, on a terminal
+/// `rows` high.
+fn exit_sequence(alternate: bool, rows: u16) -> String {
+    // on the main screen the interface stays, with the shell's prompt coming after it
+    let screen = match alternate {
+        true => ToMainScreen.to_string(),
+        false => format!("{}\r\n", cursor::Goto(1, rows)),
+    };
+    format!("{}{}{}", EXIT_MOUSE, screen, cursor::Show)
+}
+
+fn rows() -> u16 {
+    terminal_size().map_or(24, |(_, h)| h)
+}
+
+fn write_all(text: &str) {
+    let mut buf = text.as_bytes();
+    while !buf.is_empty() {
+        let n = unsafe { libc::write(libc::STDOUT_FILENO, buf.as_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            return;
+        }
+        buf = &buf[n as usize..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_only_undoes_once() {
+        // a state of its own, the terminal the tests run in is left alone
+        let active = Active::new();
+        assert_eq!(active.finish(), None);
+
+        active.start(true);
+        assert_eq!(active.finish(), Some(true));
+        assert_eq!(active.finish(), None);
+
+        active.start(false);
+        assert!(active.is_on());
+        assert_eq!(active.finish(), Some(false));
+        assert!(!active.is_on());
+    }
+
+    #[test]
+    fn exit_leaves_the_screen_it_entered() {
+        let alternate = exit_sequence(true, 24);
+        assert!(alternate.starts_with(EXIT_MOUSE));
+        assert!(alternate.contains(&ToMainScreen.to_string()));
+        assert!(alternate.ends_with(&cursor::Show.to_string()));
+
+        // the main screen ends on the last row instead, with the prompt on a line of its own
+        let main = exit_sequence(false, 30);
+        assert!(!main.contains(&ToMainScreen.to_string()));
+        assert!(main.contains(&format!("{}\r\n", cursor::Goto(1, 30))));
+    }
+}
@@ -0,0 +1,423 @@
+use clap::ValueEnum;
+use std::env;
+use termion::{
+    color::{self, AnsiValue, Bg, Fg},
+    style,
+};
+
+// what the first sixteen palette entries look like in xterm, other terminals are close enough
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+// steps of the 6x6x6 color cube that follows them
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// When to use colors, `--color` picks one.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Depth {
+    Monochrome,
+    Ansi8,
+    Ansi16,
+    Ansi256,
+    Truecolor,
+}
+
+impl Depth {
+    /// What `$COLORTERM` and `$TERM` say the terminal can do.
+    pub fn detect() -> Self {
+        Self::from_env(|name| env::var(name).ok())
+    }
+
+    // the variables come from `var` so they can be given without touching the environment
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let colorterm = var("COLORTERM").unwrap_or_default();
+        let term = var("TERM").unwrap_or_default();
+
+        match term.as_str() {
+            "" | "dumb" | "vt100" | "vt102" | "vt220" => Depth::Monochrome,
+            _ if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") => {
+                Depth::Truecolor
+            }
+            _ if term.contains("256color") => Depth::Ansi256,
+            // the console and old screen sessions don't have the bright variants
+            "linux" | "cons25" | "ansi" | "screen" => Depth::Ansi8,
+            _ if term.starts_with("screen.") => Depth::Ansi8,
+            _ => Depth::Ansi16,
+        }
+    }
+}
+
+/// A color from the palette, either by its index or as 24-bit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Paint {
+    Index(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Paint {
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Paint::Rgb(r, g, b) => (r, g, b),
+            Paint::Index(i @ 0..=15) => ANSI_RGB[i as usize],
+            Paint::Index(i @ 16..=231) => {
+                let i = (i - 16) as usize;
+                (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+            }
+            Paint::Index(i) => {
+                let level = 8 + (i - 232) * 10;
+                (level, level, level)
+            }
+        }
+    }
+
+    /// The closest color the terminal can show.
+    fn fit(self, depth: Depth) -> Self {
+        match (self, depth) {
+            (paint, Depth::Truecolor | Depth::Monochrome) => paint,
+            (Paint::Index(i), Depth::Ansi256) => Paint::Index(i),
+            // the first sixteen are left out, terminals are free to change them
+            (paint, Depth::Ansi256) => Paint::Index(nearest(paint.rgb(), 16..=255)),
+            (paint, depth) => {
+                let i = match paint {
+                    Paint::Index(i @ 0..=15) => i,
+                    paint => nearest(paint.rgb(), 0..=15),
+                };
+                match (depth, i) {
+                    // bright black is a gray, closer to white than to black
+                    (Depth::Ansi8, 8) => Paint::Index(7),
+                    (Depth::Ansi8, 9..) => Paint::Index(i - 8),
+                    _ => Paint::Index(i),
+                }
+            }
+        }
+    }
+
+    fn escape(self, background: bool) -> String {
+        match (self, background) {
+            (Paint::Rgb(r, g, b), false) => Fg(color::Rgb(r, g, b)).to_string(),
+            (Paint::Rgb(r, g, b), true) => Bg(color::Rgb(r, g, b)).to_string(),
+            // the basic codes, which terminals without a 256-color palette understand too
+            (Paint::Index(i @ 0..=15), background) => {
+                let base = match background {
+                    true => 40,
+                    false => 30,
+                };
+                let code = match i {
+                    0..=7 => base + i,
+                    _ => base + 60 + i - 8,
+                };
+                format!("\x1B[{}m", code)
+            }
+            (Paint::Index(i), false) => Fg(AnsiValue(i)).to_string(),
+            (Paint::Index(i), true) => Bg(AnsiValue(i)).to_string(),
+        }
+    }
+}
+
+/// Colors of each part of the interface, before they're fitted to the terminal.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub header: Paint,
+    pub title: Paint,
+    pub list: Paint,
+    pub pointer_fg: Paint,
+    pub pointer_bg: Paint,
+    pub visual_bg: Paint,
+    // background of every other row, none unless configured
+    pub stripe: Option<Paint>,
+    pub footer: Paint,
+    pub error: Paint,
+    pub warning: Paint,
+    // the part of a name that a search or filter matched
+    pub highlight: Paint,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            header: Paint::Index(10),
+            title: Paint::Index(7),
+            list: Paint::Index(11),
+            pointer_fg: Paint::Index(7),
+            pointer_bg: Paint::Index(8),
+            visual_bg: Paint::Index(4),
+            stripe: None,
+            footer: Paint::Index(12),
+            error: Paint::Index(9),
+            warning: Paint::Index(11),
+            highlight: Paint::Index(13),
+        }
+    }
+}
+
+impl Palette {
+    /// Sets the element called `name` to `paint`, returning false for unknown elements.
+    pub fn set(&mut self, name: &str, paint: Paint) -> bool {
+        let slot = match name {
+            "header" => &mut self.header,
+            "title" => &mut self.title,
+            "list" => &mut self.list,
+            "pointer_fg" => &mut self.pointer_fg,
+            "pointer_bg" => &mut self.pointer_bg,
+            "visual_bg" => &mut self.visual_bg,
+            "stripe" => {
+                self.stripe = Some(paint);
+                return true;
+            }
+            "footer" => &mut self.footer,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "highlight" => &mut self.highlight,
+            _ => return false,
+        };
+        *slot = paint;
+
+        true
+    }
+}
+
+/// Escape sequences for the colors of each part of the interface.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub header: String,
+    pub title: String,
+    pub list: String,
+    pub pointer_fg: String,
+    pub pointer_bg: String,
+    pub visual_bg: String,
+    // background of every other row, none unless configured
+    pub stripe: String,
+    pub footer: String,
+    pub error: String,
+    pub warning: String,
+    // the part of a name that a search or filter matched
+    pub highlight: String,
+    pub bold: String,
+    pub dim: String,
+    pub italic: String,
+    pub underline: String,
+    pub reset: String,
+    // without colors the pointer row is marked in the margin instead
+    pub marker: bool,
+}
+
+impl Theme {
+    /// The palette in the closest colors a terminal with `depth` can show.
+    pub fn new(palette: &Palette, depth: Depth) -> Self {
+        if depth == Depth::Monochrome {
+            return Self::monochrome();
+        }
+
+        let fg = |paint: Paint| paint.fit(depth).escape(false);
+        let bg = |paint: Paint| paint.fit(depth).escape(true);
+        // a pointer that lands on its own background after fitting gives way to black or white
+        let pointer_bg = palette.pointer_bg.fit(depth);
+        let pointer_fg = match palette.pointer_fg.fit(depth) {
+            fg if fg == pointer_bg && fg == Paint::Index(0) => Paint::Index(7),
+            fg if fg == pointer_bg => Paint::Index(0),
+            fg => fg,
+        };
+
+        Self {
+            header: fg(palette.header),
+            title: fg(palette.title),
+            list: fg(palette.list),
+            pointer_fg: pointer_fg.escape(false),
+            pointer_bg: pointer_bg.escape(true),
+            visual_bg: bg(palette.visual_bg),
+            stripe: palette.stripe.map(bg).unwrap_or_default(),
+            footer: fg(palette.footer),
+            error: fg(palette.error),
+            warning: fg(palette.warning),
+            highlight: fg(palette.highlight),
+            bold: style::Bold.to_string(),
+            dim: style::Faint.to_string(),
+            italic: style::Italic.to_string(),
+            underline: style::Underline.to_string(),
+            reset: style::Reset.to_string(),
+            marker: false,
+        }
+    }
+
+    /// No color or style sequences at all, for `NO_COLOR` and `--color=never`.
+    fn monochrome() -> Self {
+        Self {
+            header: String::new(),
+            title: String::new(),
+            list: String::new(),
+            pointer_fg: String::new(),
+            pointer_bg: String::new(),
+            visual_bg: String::new(),
+            stripe: String::new(),
+            footer: String::new(),
+            error: String::new(),
+            warning: String::new(),
+            highlight: String::new(),
+            bold: String::new(),
+            dim: String::new(),
+            italic: String::new(),
+            underline: String::new(),
+            reset: String::new(),
+            marker: true,
+        }
+    }
+}
+
+/// Index of a named ANSI color, in the spelling termion uses.
+pub fn color_index(name: &str) -> Option<u8> {
+    // the bright variants follow the eight basic colors in the palette
+    const NAMES: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+
+    let name = name.to_lowercase();
+    let (base, offset) = match name.strip_prefix("light") {
+        Some(base) => (base, 8),
+        None => (name.as_str(), 0),
+    };
+
+    NAMES
+        .iter()
+        .position(|n| *n == base)
+        .map(|i| i as u8 + offset)
+}
+
+/// A `#rrggbb` color.
+pub fn hex_color(text: &str) -> Option<Paint> {
+    // from_str_radix takes a sign as well, so the digits are checked first
+    let hex = text
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()))?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+    Some(Paint::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// The palette entry in `indices` closest to `rgb`.
+fn nearest(rgb: (u8, u8, u8), indices: std::ops::RangeInclusive<u8>) -> u8 {
+    let distance = |i: &u8| {
+        let (r, g, b) = Paint::Index(*i).rgb();
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+
+    indices.min_by_key(distance).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(colorterm: &str, term: &str) -> Depth {
+        Depth::from_env(|name| match name {
+            "COLORTERM" => Some(colorterm.to_string()),
+            "TERM" => Some(term.to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn depth_from_the_environment() {
+        assert_eq!(depth("", ""), Depth::Monochrome);
+        assert_eq!(depth("truecolor", "dumb"), Depth::Monochrome);
+        assert_eq!(depth("truecolor", "xterm"), Depth::Truecolor);
+        assert_eq!(depth("24bit", "xterm-256color"), Depth::Truecolor);
+        assert_eq!(depth("", "xterm-direct"), Depth::Truecolor);
+        assert_eq!(depth("", "xterm-256color"), Depth::Ansi256);
+        assert_eq!(depth("", "screen.xterm-256color"), Depth::Ansi256);
+        assert_eq!(depth("", "screen"), Depth::Ansi8);
+        assert_eq!(depth("", "screen.linux"), Depth::Ansi8);
+        assert_eq!(depth("", "linux"), Depth::Ansi8);
+        assert_eq!(depth("", "xterm"), Depth::Ansi16);
+        // unset is the same as empty
+        assert_eq!(Depth::from_env(|_| None), Depth::Monochrome);
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(hex_color("#ff8000"), Some(Paint::Rgb(255, 128, 0)));
+        assert_eq!(hex_color("#00AAff"), Some(Paint::Rgb(0, 170, 255)));
+        assert_eq!(hex_color("ff8000"), None);
+        assert_eq!(hex_color("#ff800"), None);
+        assert_eq!(hex_color("#ff80000"), None);
+        assert_eq!(hex_color("#ff80zz"), None);
+        assert_eq!(hex_color("#+f+f+f"), None);
+        // six bytes, but not six hex digits
+        assert_eq!(hex_color("#\u{e9}\u{e9}\u{e9}"), None);
+    }
+
+    #[test]
+    fn color_names() {
+        assert_eq!(color_index("black"), Some(0));
+        assert_eq!(color_index("Blue"), Some(4));
+        assert_eq!(color_index("lightred"), Some(9));
+        assert_eq!(color_index("lightwhite"), Some(15));
+        assert_eq!(color_index("purple"), None);
+        assert_eq!(color_index("light"), None);
+        assert_eq!(color_index("light red"), None);
+        assert_eq!(color_index(""), None);
+    }
+
+    #[test]
+    fn fitting_to_256_colors() {
+        let fit = |paint: Paint| paint.fit(Depth::Ansi256);
+        assert_eq!(fit(Paint::Rgb(255, 0, 0)), Paint::Index(196));
+        assert_eq!(fit(Paint::Rgb(255, 128, 0)), Paint::Index(208));
+        // grays land on the gray ramp rather than the cube
+        assert_eq!(fit(Paint::Rgb(128, 128, 128)), Paint::Index(244));
+        assert_eq!(fit(Paint::Rgb(0, 0, 0)), Paint::Index(16));
+        assert_eq!(fit(Paint::Index(200)), Paint::Index(200));
+        assert_eq!(fit(Paint::Index(3)), Paint::Index(3));
+    }
+
+    #[test]
+    fn fitting_to_16_and_8_colors() {
+        let fit = |paint: Paint| (paint.fit(Depth::Ansi16), paint.fit(Depth::Ansi8));
+        let both = |i: u8, j: u8| (Paint::Index(i), Paint::Index(j));
+        assert_eq!(fit(Paint::Rgb(255, 0, 0)), both(9, 1));
+        assert_eq!(fit(Paint::Rgb(255, 128, 0)), both(3, 3));
+        assert_eq!(fit(Paint::Rgb(128, 128, 128)), both(8, 7));
+        assert_eq!(fit(Paint::Index(12)), both(12, 4));
+        assert_eq!(fit(Paint::Index(200)), both(13, 5));
+    }
+
+    #[test]
+    fn fitting_keeps_rgb_where_it_can() {
+        let paint = Paint::Rgb(1, 2, 3);
+        assert_eq!(paint.fit(Depth::Truecolor), paint);
+        assert_eq!(Paint::Index(100).fit(Depth::Truecolor), Paint::Index(100));
+    }
+
+    #[test]
+    fn nearest_stays_in_range() {
+        assert_eq!(nearest((0, 0, 0), 0..=15), 0);
+        assert_eq!(nearest((0, 0, 0), 16..=255), 16);
+        assert_eq!(nearest((255, 255, 255), 0..=15), 15);
+        assert_eq!(nearest((255, 255, 255), 16..=255), 231);
+        assert_eq!(nearest((1, 2, 3), 5..=5), 5);
+    }
+}